@@ -0,0 +1,14 @@
+#![cfg_attr(test, feature(test))]
+//! Search and replace in files, including text aligned on columns across lines.
+//!
+//! Runs are described with a `Ved`, which returns a `Report` of what was done to each file. The
+//! `replacer` module gives access to the lower level functions, working on streams.
+
+pub mod replacer;
+mod teereader;
+
+pub use replacer::{
+    CancelToken, Counts, Diff, Error, ErrorPolicy, FileReport, FileStats, Found, Hit, MatchInfo,
+    Matcher, Observer, Operation, Options, Progress, Rectangle, Report, Result, Search,
+    SearchConfig, SearchSpec, Selection, SkipReason, Totals, Ved,
+};
@@ -1,38 +1,506 @@
-#![feature(test)]
+mod output;
+mod status;
 
-mod replacer;
-mod teereader;
+use clap::{ArgGroup, Parser};
+use output::Format;
+use status::StatusLine;
+use std::io::{self, BufReader, IsTerminal, Stderr};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use ved::replacer::{
+    self, vertical, Address, Align, ColumnMode, ColumnRange, ErrorPolicy, FileReport, NumberedRule,
+    Observer, Options, Prompt, Rectangle, Report, SearchConfig, Selection, Separator, SkipReason,
+    Ved,
+};
 
-use clap::Parser;
-use std::path::Path;
+/// The arguments that cannot be used with the rectangle edits.
+const RECTANGLE_CONFLICTS: [&str; 4] = ["search", "check", "interactive", "vertical"];
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(group(ArgGroup::new("rectangle").args(["delete_columns", "insert_column", "replace_columns"])))]
 struct Args {
-    #[arg(short, long)]
-    search: String,
+    /// The text to search, repeated for each row of a block
+    #[arg(short, long, required_unless_present = "rectangle")]
+    search: Vec<String>,
 
-    #[arg(short, long)]
-    replace: String,
+    /// The replacement text, repeated for each row of a block
+    #[arg(short, long, required_unless_present_any = ["check", "delete_columns"])]
+    replace: Vec<String>,
 
+    /// A file, a directory (processed recursively) or a glob
     #[arg(short, long, default_value = ".")]
     path: String,
+
+    /// Stop at the first file that cannot be processed
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Only report the matches without writing anything, exit with 1 if there are any
+    #[arg(long)]
+    check: bool,
+
+    /// Ask for confirmation before each replacement
+    #[arg(short, long, conflicts_with = "check")]
+    interactive: bool,
+
+    /// Only replace in the lines of this address: `N`, `N,M`, `/START/,/END/` or a mix of them, a
+    /// lone `/MARKER/` being the lines containing MARKER
+    #[arg(long, value_name = "ADDRESS")]
+    address: Vec<Address>,
+
+    /// Exclude the lines of the bounds from the addresses
+    #[arg(long, requires = "address")]
+    exclusive: bool,
+
+    /// Replace at most this number of occurrences in each file
+    #[arg(long, value_name = "N")]
+    max_count: Option<usize>,
+
+    /// Replace at most this number of occurrences over all the files
+    #[arg(long, value_name = "N")]
+    max_total: Option<usize>,
+
+    /// Only replace the nth occurrence of each file, starting at 1
+    #[arg(long, value_name = "N")]
+    nth: Option<NonZeroUsize>,
+
+    /// Only replace the first occurrence of each line
+    #[arg(long)]
+    first_per_line: bool,
+
+    /// How the columns of block rows are counted: `bytes`, `chars` or `width` (on a terminal)
+    #[arg(long, value_name = "MODE", default_value = "bytes")]
+    columns: ColumnMode,
+
+    /// Expand tabs to this tab stop when counting the columns of block rows, and keep the
+    /// alignment of the tabs following a replacement
+    #[arg(long, value_name = "N")]
+    tabstop: Option<NonZeroUsize>,
+
+    /// What ends the lines of a block: `lf`, `crlf`, `cr`, `nul` or any other text
+    #[arg(long, value_name = "SEPARATOR", default_value = "lf")]
+    separator: Separator,
+
+    /// A rule for a row of a block, starting at 1: `ROW:RULE[,RULE]` where a rule is `any` (any
+    /// line, its replacement must be empty), `optional`, `gap=N` (at most N lines skipped before
+    /// the row), or `delta=D` or `delta=MIN..MAX` (the columns of the row relative to the first
+    /// one)
+    #[arg(long, value_name = "ROW:RULES")]
+    row: Vec<NumberedRule>,
+
+    /// Read rules for the rows of a block from this file, one `ROW:RULE[,RULE]` per line as with
+    /// `--row`, skipping blank lines and `#` comments; the `--row` rules override them
+    #[arg(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+
+    /// Which column the rows of a block share: `start` or `end` (right-aligned rows)
+    #[arg(long, value_name = "ALIGN", default_value = "start")]
+    align: Align,
+
+    /// Only match blocks whose rows are aligned at most at this column
+    #[arg(long, value_name = "N")]
+    max_column: Option<usize>,
+
+    /// Keep the text after each replacement at the same column, by padding the replacement
+    /// with spaces or by removing the spaces after it
+    #[arg(long)]
+    keep_columns: bool,
+
+    /// Search the text written vertically, one character per line at the same column, and
+    /// replace each character with the one at the same rank in the replacement
+    #[arg(long)]
+    vertical: bool,
+
+    /// Delete these columns of every line instead of searching: `N` or `N-M`, starting at 1
+    #[arg(long, value_name = "COLUMNS", conflicts_with_all = RECTANGLE_CONFLICTS)]
+    #[arg(conflicts_with = "replace")]
+    delete_columns: Option<ColumnRange>,
+
+    /// Insert the replacement text before this column of every line instead of searching,
+    /// starting at 1
+    #[arg(long, value_name = "N", conflicts_with_all = RECTANGLE_CONFLICTS)]
+    insert_column: Option<NonZeroUsize>,
+
+    /// Replace these columns of every line with the replacement text instead of searching: `N`
+    /// or `N-M`, starting at 1
+    #[arg(long, value_name = "COLUMNS", conflicts_with_all = RECTANGLE_CONFLICTS)]
+    replace_columns: Option<ColumnRange>,
+
+    /// Read `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` as escapes in the texts to search and to
+    /// replace
+    #[arg(short, long, conflicts_with = "vertical")]
+    escapes: bool,
+
+    /// Read the texts to search and to replace as hexadecimal bytes, like `DEADBEEF`
+    #[arg(long, conflicts_with_all = ["escapes", "vertical"])]
+    hex: bool,
+
+    /// Largest size of the search window in bytes, a match must fit in it [default: 4194304]
+    #[arg(long, value_name = "BYTES")]
+    window: Option<NonZeroUsize>,
+
+    /// Skip the files taking more than this number of seconds to search, leaving them as they were
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// Print the statistics of each file and their totals after the run
+    #[arg(long)]
+    stats: bool,
+
+    /// Show the progress on the standard error: the files done, the bytes processed, the
+    /// throughput and the time left for the files started
+    #[arg(long, conflicts_with = "interactive")]
+    progress: bool,
+
+    /// Output format of the check report and of the statistics
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
     run(args)
 }
 
-fn run(args: Args) {
-    let result = replacer::replace_glob(&vec![&args.search], &vec![&args.replace], &args.path);
+fn run(mut args: Args) -> ExitCode {
+    if args.vertical {
+        if args.search.len() != 1 || args.replace.len() > 1 {
+            println!("cannot search vertically: give a single text to search and to replace");
+            return ExitCode::FAILURE;
+        }
+        args.search = vertical(&args.search[0])
+            .into_iter()
+            .map(String::from)
+            .collect();
+        args.replace = args
+            .replace
+            .iter()
+            .flat_map(|r| vertical(r))
+            .map(String::from)
+            .collect();
+    }
+    let policy = if args.fail_fast {
+        ErrorPolicy::FailFast
+    } else {
+        ErrorPolicy::Continue
+    };
+    let addresses = args
+        .address
+        .iter()
+        .map(|address| Address {
+            inclusive: !args.exclusive,
+            ..address.clone()
+        })
+        .collect();
+    let selection = Selection {
+        addresses,
+        first_per_line: args.first_per_line,
+        nth: args.nth.map(NonZeroUsize::get),
+        max_per_file: args.max_count,
+        max_total: args.max_total,
+    };
+    let rules = match row_rules(&args) {
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("invalid rules: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let status = args.progress.then(|| {
+        if io::stderr().is_terminal() {
+            Arc::new(StatusLine::new(io::stderr()))
+        } else {
+            Arc::new(StatusLine::plain(io::stderr()))
+        }
+    });
+    let options = Options {
+        policy,
+        selection,
+        search: SearchConfig {
+            columns: args.columns,
+            tabstop: args.tabstop,
+            separator: args.separator.clone(),
+            rows: NumberedRule::collect(&rules),
+            align: args.align,
+            max_column: args.max_column,
+            window: args.window,
+            keep_columns: args.keep_columns,
+        },
+        progress: status.clone().map(|status| Observer::new(status)),
+        cancel: None,
+        timeout: args.timeout,
+    };
+    let texts = match Texts::decode(&args) {
+        Ok(texts) => texts,
+        Err(e) => {
+            println!("invalid text: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(rectangle) = rectangle(&args, &texts) {
+        run_edit(&args, &rectangle, &options, status.as_deref())
+    } else if let Some(row) = texts.search.iter().position(Vec::is_empty) {
+        println!("cannot search: row {} is empty", row + 1);
+        ExitCode::FAILURE
+    } else if args.check {
+        run_check(&args, &texts, &options, status.as_deref())
+    } else if args.replace.len() != args.search.len() {
+        println!(
+            "cannot replace: {} rows to search but {} replacements",
+            args.search.len(),
+            args.replace.len()
+        );
+        ExitCode::FAILURE
+    } else if let Some(row) = options.search.replaced_any_row(&texts.replace) {
+        println!("cannot replace row {row}: it matches any line, its replacement must be empty");
+        ExitCode::FAILURE
+    } else {
+        run_replace(&args, &texts, &options, status.as_deref())
+    }
+}
+
+/// Returns the rules of the rows of a block, the ones of the rules file followed by the ones of
+/// `--row`.
+fn row_rules(args: &Args) -> Result<Vec<NumberedRule>, String> {
+    let mut rules = match &args.rules {
+        None => Vec::new(),
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+            NumberedRule::parse_file(&text)?
+        }
+    };
+    rules.extend(args.row.iter().cloned());
+    Ok(rules)
+}
+
+/// The texts to search and to replace, as the bytes they stand for.
+struct Texts {
+    search: Vec<Vec<u8>>,
+    replace: Vec<Vec<u8>>,
+}
+
+impl Texts {
+    /// Decodes the texts of the arguments, according to their escape or hexadecimal syntax.
+    fn decode(args: &Args) -> Result<Self, String> {
+        let decode = |text: &String| {
+            if args.hex {
+                replacer::parse_hex(text)
+            } else if args.escapes {
+                replacer::unescape(text)
+            } else {
+                Ok(text.as_bytes().to_vec())
+            }
+        };
+        Ok(Self {
+            search: args.search.iter().map(decode).collect::<Result<_, _>>()?,
+            replace: args.replace.iter().map(decode).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Returns the rows of the block to search along with their replacements.
+    fn rows(&self) -> Vec<(&[u8], &[u8])> {
+        let replacements = self.replace.iter().map(Vec::as_slice);
+        let replacements = replacements.chain(std::iter::repeat(&[][..]));
+        self.search
+            .iter()
+            .map(Vec::as_slice)
+            .zip(replacements)
+            .collect()
+    }
+}
 
+/// Returns the rectangle edit asked for, if any.
+fn rectangle(args: &Args, texts: &Texts) -> Option<Rectangle> {
+    let text = texts.replace.concat();
+    if let Some(range) = args.delete_columns {
+        Some(Rectangle {
+            start: range.start,
+            end: range.end,
+            text: Vec::new(),
+        })
+    } else if let Some(column) = args.insert_column {
+        Some(Rectangle {
+            start: column.get() - 1,
+            end: column.get() - 1,
+            text,
+        })
+    } else {
+        args.replace_columns.map(|range| Rectangle {
+            start: range.start,
+            end: range.end,
+            text,
+        })
+    }
+}
+
+fn run_edit(
+    args: &Args,
+    rectangle: &Rectangle,
+    options: &Options,
+    status: Option<&StatusLine<Stderr>>,
+) -> ExitCode {
+    if args.replace.len() > 1 {
+        println!("cannot edit: give a single replacement text");
+        return ExitCode::FAILURE;
+    }
+    let ved = Ved::new()
+        .rectangle(rectangle.clone())
+        .path(&args.path)
+        .options(options.clone());
+    match run_ved(&ved, status) {
+        Ok(report) => {
+            for e in &report.errors {
+                println!("cannot edit: {}", e)
+            }
+            for path in timed_out(&report) {
+                eprintln!(
+                    "cannot edit: {}: took more than the timeout",
+                    path.display()
+                )
+            }
+            write_stats(args, &report);
+        }
+        Err(e) => println!("cannot edit: {}", e),
+    }
+    ExitCode::SUCCESS
+}
+
+fn run_replace(
+    args: &Args,
+    texts: &Texts,
+    options: &Options,
+    status: Option<&StatusLine<Stderr>>,
+) -> ExitCode {
+    let ved = Ved::new()
+        .block(texts.rows())
+        .path(&args.path)
+        .options(options.clone());
+    let result = if args.interactive {
+        let mut prompt = Prompt::new(BufReader::new(io::stdin()), io::stdout());
+        ved.run_interactive(&mut prompt)
+    } else {
+        run_ved(&ved, status)
+    };
     match result {
-        Ok(_) => {}
+        Ok(report) => {
+            for e in &report.errors {
+                println!("cannot replace: {}", e)
+            }
+            for path in timed_out(&report) {
+                eprintln!(
+                    "cannot replace: {}: took more than the timeout",
+                    path.display()
+                )
+            }
+            let skipped = report.nb_skipped();
+            if skipped > 0 {
+                println!("{skipped} matches skipped")
+            }
+            write_stats(args, &report);
+        }
         Err(e) => {
             println!("cannot replace: {}", e)
         }
     }
+    ExitCode::SUCCESS
+}
+
+/// Runs ved, then ends the status line of its progress so that the messages that follow start on
+/// a line of their own.
+fn run_ved(ved: &Ved, status: Option<&StatusLine<Stderr>>) -> ved::Result<Report> {
+    let result = ved.run();
+    if let Some(status) = status {
+        status.finish();
+    }
+    result
+}
+
+/// Returns the files that were skipped because searching them took more than the timeout.
+fn timed_out(report: &Report) -> impl Iterator<Item = &Path> {
+    let files = report.files.iter();
+    let files = files.filter(|f| f.skipped == Some(SkipReason::Timeout));
+    files.map(|f| f.path.as_path())
+}
+
+/// Parses a number of seconds, which may have a fractional part.
+fn parse_seconds(text: &str) -> Result<Duration, String> {
+    let seconds: f64 = text.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{e}"))
+}
+
+/// Writes the statistics of a run on the standard output, when they were asked for.
+fn write_stats(args: &Args, report: &Report) {
+    if args.stats {
+        if let Err(e) = output::write_stats(&mut io::stdout(), args.format, report) {
+            eprintln!("cannot write statistics: {}", e);
+        }
+    }
+}
+
+/// Exit code when a check found at least one match.
+const CHECK_FOUND: u8 = 1;
+/// Exit code when a check could not be completed.
+const CHECK_ERROR: u8 = 2;
+
+fn run_check(
+    args: &Args,
+    texts: &Texts,
+    options: &Options,
+    status: Option<&StatusLine<Stderr>>,
+) -> ExitCode {
+    let ved = Ved::new()
+        .block(texts.rows())
+        .path(&args.path)
+        .options(options.clone())
+        .dry_run(true);
+
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut skipped = 0;
+    let result = run_ved(&ved, status);
+    match &result {
+        Ok(report) => {
+            skipped = report.nb_skipped();
+            let hits = |f: &FileReport| (f.path.clone(), f.hits.clone());
+            files = report.files.iter().map(hits).collect();
+            errors = report.errors.iter().map(ToString::to_string).collect();
+            let timeout = |path: &Path| format!("{}: took more than the timeout", path.display());
+            errors.extend(timed_out(report).map(timeout));
+        }
+        Err(e) => errors.push(e.to_string()),
+    }
+    for e in &errors {
+        eprintln!("cannot check: {}", e)
+    }
+    if skipped > 0 {
+        eprintln!("{skipped} matches skipped")
+    }
+    let stats = result.as_ref().ok().filter(|_| args.stats);
+    let report = output::write_check(
+        &mut io::stdout(),
+        args.format,
+        &files,
+        skipped,
+        &errors,
+        stats,
+    );
+    if let Err(e) = report {
+        eprintln!("cannot write report: {}", e);
+        return ExitCode::from(CHECK_ERROR);
+    }
+
+    if !errors.is_empty() {
+        ExitCode::from(CHECK_ERROR)
+    } else if files.iter().any(|(_, hits)| !hits.is_empty()) {
+        ExitCode::from(CHECK_FOUND)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
 #[cfg(test)]
@@ -40,20 +508,280 @@ mod tests {
     use super::*;
     use std::fs;
 
+    fn args(search: &str, replace: Option<&str>, path: &Path) -> Args {
+        Args {
+            search: vec![search.to_string()],
+            replace: replace.into_iter().map(|r| r.to_string()).collect(),
+            path: path.to_str().unwrap().to_owned(),
+            fail_fast: false,
+            check: false,
+            interactive: false,
+            address: Vec::new(),
+            exclusive: false,
+            max_count: None,
+            max_total: None,
+            nth: None,
+            first_per_line: false,
+            columns: ColumnMode::Bytes,
+            tabstop: None,
+            separator: Separator::default(),
+            row: Vec::new(),
+            rules: None,
+            align: Align::Start,
+            max_column: None,
+            keep_columns: false,
+            vertical: false,
+            delete_columns: None,
+            insert_column: None,
+            replace_columns: None,
+            escapes: false,
+            hex: false,
+            window: None,
+            stats: false,
+            progress: false,
+            timeout: None,
+            format: Format::Text,
+        }
+    }
+
     #[test]
     fn test_run() {
         let dir = temp_dir();
         let path = dir.path().join("file");
         write_file(&path, "aaaaa");
-        run(Args {
-            search: "a".to_string(),
-            replace: "b".to_string(),
-            path: path.to_str().unwrap().to_owned(),
-        });
+        run(args("a", Some("b"), &path));
         let content = file_content(&path);
         assert_eq!(content, "bbbbb");
     }
 
+    #[test]
+    fn test_check_found() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "aaaaa");
+        let mut check_args = args("a", Some("b"), dir.path());
+        check_args.check = true;
+        assert_eq!(run(check_args), ExitCode::from(CHECK_FOUND));
+        let content = file_content(&path);
+        assert_eq!(content, "aaaaa");
+    }
+
+    #[test]
+    fn test_check_clean() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "aaaaa");
+        let mut check_args = args("b", None, &path);
+        check_args.check = true;
+        check_args.format = Format::Json;
+        assert_eq!(run(check_args), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn test_run_max_count() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "aaaaa");
+        let mut max_args = args("a", Some("b"), &path);
+        max_args.max_count = Some(2);
+        run(max_args);
+        let content = file_content(&path);
+        assert_eq!(content, "bbaaa");
+    }
+
+    #[test]
+    fn test_run_address() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "a\n// BEGIN\na\n// END\na");
+        let mut address_args = args("a", Some("b"), &path);
+        address_args.address = vec!["/BEGIN/,/END/".parse().unwrap()];
+        address_args.exclusive = true;
+        run(address_args);
+        let content = file_content(&path);
+        assert_eq!(content, "a\n// BEGIN\nb\n// END\na");
+    }
+
+    #[test]
+    fn test_run_block_columns() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "été who\nabc abba");
+        let mut block_args = args("who", Some("WHO"), &path);
+        block_args.search.push("abba".to_string());
+        block_args.replace.push("ABBA".to_string());
+        run(block_args);
+        assert_eq!(file_content(&path), "été who\nabc abba");
+
+        let mut block_args = args("who", Some("WHO"), &path);
+        block_args.search.push("abba".to_string());
+        block_args.replace.push("ABBA".to_string());
+        block_args.columns = ColumnMode::Chars;
+        run(block_args);
+        assert_eq!(file_content(&path), "été WHO\nabc ABBA");
+    }
+
+    #[test]
+    fn test_run_block_any_row() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "| a |\n| x |\n| c |");
+        let block_args = |any_replacement: &str| {
+            let mut block_args = args("a", Some("A"), &path);
+            block_args.search.extend(["?".to_string(), "c".to_string()]);
+            block_args
+                .replace
+                .extend([any_replacement.to_string(), "C".to_string()]);
+            block_args.row = vec!["2:any".parse().unwrap()];
+            block_args
+        };
+        assert_eq!(run(block_args("?")), ExitCode::FAILURE);
+        assert_eq!(file_content(&path), "| a |\n| x |\n| c |");
+        run(block_args(""));
+        assert_eq!(file_content(&path), "| A |\n| x |\n| C |");
+    }
+
+    #[test]
+    fn test_run_empty_pattern() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba");
+        assert_eq!(run(args("", Some("x"), &path)), ExitCode::FAILURE);
+        assert_eq!(file_content(&path), "abba");
+        let mut check_args = args("", None, &path);
+        check_args.check = true;
+        assert_eq!(run(check_args), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_run_rules_file() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "a\n    b\na\nb");
+        let rules = dir.path().join("rules");
+        write_file(&rules, "# the body is indented\n2:delta=+4\n");
+        let block_args = |rules: &Path| {
+            let mut block_args = args("a", Some("A"), &path);
+            block_args.search.push("b".to_string());
+            block_args.replace.push("B".to_string());
+            block_args.rules = Some(rules.to_path_buf());
+            block_args
+        };
+        run(block_args(&rules));
+        assert_eq!(file_content(&path), "A\n    B\na\nb");
+
+        write_file(&rules, "2:delta=+x");
+        assert_eq!(run(block_args(&rules)), ExitCode::FAILURE);
+        let missing = dir.path().join("missing");
+        assert_eq!(run(block_args(&missing)), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_run_vertical() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "| a  |\n| b  |\n| ba |\n| a  |");
+        let mut vertical_args = args("abba", Some("ABBA"), &path);
+        vertical_args.vertical = true;
+        run(vertical_args);
+        assert_eq!(file_content(&path), "| A  |\n| B  |\n| Ba |\n| A  |");
+
+        let mut vertical_args = args("ab", Some("ABC"), &path);
+        vertical_args.vertical = true;
+        assert_eq!(run(vertical_args), ExitCode::FAILURE);
+
+        // the words down a column overlap, the first one is replaced
+        write_file(&path, "a\na\na\n a\n a\n a\n a");
+        let mut vertical_args = args("aa", Some("bc"), &path);
+        vertical_args.vertical = true;
+        assert_eq!(run(vertical_args), ExitCode::SUCCESS);
+        assert_eq!(file_content(&path), "b\nc\na\n b\n c\n b\n c");
+    }
+
+    #[test]
+    fn test_run_keep_columns() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "| abba  | 1 |\n| abba  | 2 |");
+        let mut block_args = args("abba", Some("ab"), &path);
+        block_args.search.push("abba".to_string());
+        block_args.replace.push("abbabba".to_string());
+        block_args.keep_columns = true;
+        run(block_args);
+        assert_eq!(file_content(&path), "| ab    | 1 |\n| abbabba| 2 |");
+    }
+
+    #[test]
+    fn test_run_rectangle() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "id  name\n1   abba\n2   toto");
+        let mut edit_args = args("", None, &path);
+        edit_args.search = Vec::new();
+        edit_args.delete_columns = Some("3-4".parse().unwrap());
+        run(edit_args);
+        assert_eq!(file_content(&path), "idname\n1 abba\n2 toto");
+
+        let mut edit_args = args("", Some("|"), &path);
+        edit_args.search = Vec::new();
+        edit_args.insert_column = NonZeroUsize::new(3);
+        edit_args.address = vec!["2,3".parse().unwrap()];
+        run(edit_args);
+        assert_eq!(file_content(&path), "idname\n1 |abba\n2 |toto");
+    }
+
+    #[test]
+    fn test_run_bytes() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        fs::write(&path, b"ab\x00\xff\ncd").unwrap();
+        let mut escape_args = args(r"\x00\xff", Some(r"\t"), &path);
+        escape_args.escapes = true;
+        run(escape_args);
+        assert_eq!(fs::read(&path).unwrap(), b"ab\t\ncd");
+
+        let mut hex_args = args("09 0a", Some("00"), &path);
+        hex_args.hex = true;
+        run(hex_args);
+        assert_eq!(fs::read(&path).unwrap(), b"ab\x00cd");
+
+        let mut hex_args = args("0", Some("00"), &path);
+        hex_args.hex = true;
+        assert_eq!(run(hex_args), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba");
+        for format in [Format::Text, Format::Json] {
+            let mut stats_args = args("abba", Some("toto"), &path);
+            stats_args.stats = true;
+            stats_args.format = format;
+            assert_eq!(run(stats_args), ExitCode::SUCCESS);
+        }
+        assert_eq!(file_content(&path), "toto");
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("1.5"), Ok(Duration::from_millis(1500)));
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
+
+    #[test]
+    fn test_args_rectangle_conflicts() {
+        let parse = |args: &[&str]| Args::try_parse_from([&["ved"], args].concat());
+        assert!(parse(&["--delete-columns", "1-2"]).is_ok());
+        assert!(parse(&["--delete-columns", "1-2", "--insert-column", "3"]).is_err());
+        assert!(parse(&["--delete-columns", "1-2", "-s", "a"]).is_err());
+        assert!(parse(&["--delete-columns", "1-2", "-r", "a"]).is_err());
+        assert!(parse(&["--insert-column", "2", "-r", "a"]).is_ok());
+        assert!(parse(&["-r", "a"]).is_err());
+    }
+
     fn temp_dir() -> tempfile::TempDir {
         let result = tempfile::tempdir();
         assert!(result.is_ok());
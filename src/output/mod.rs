@@ -0,0 +1,328 @@
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use ved::{FileReport, Hit, Report, Totals};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+    /// One `path:line:column` line per match
+    #[default]
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+/// Writes the places where the patterns were found, and the files that could not be checked.
+///
+/// The statistics of the run, if any, follow the matches; in JSON, they are part of the same
+/// object, which stays the only one written.
+pub fn write_check<W: Write>(
+    output: &mut W,
+    format: Format,
+    files: &[(PathBuf, Vec<Hit>)],
+    skipped: usize,
+    errors: &[String],
+    stats: Option<&Report>,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            write_check_text(output, files)?;
+            match stats {
+                Some(report) => write_stats_text(output, report),
+                None => Ok(()),
+            }
+        }
+        Format::Json => write_check_json(output, files, skipped, errors, stats),
+    }
+}
+
+fn write_check_text<W: Write>(output: &mut W, files: &[(PathBuf, Vec<Hit>)]) -> io::Result<()> {
+    for (path, hits) in files {
+        for hit in hits {
+            writeln!(output, "{}:{}:{}", path.display(), hit.line, hit.column)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_check_json<W: Write>(
+    output: &mut W,
+    files: &[(PathBuf, Vec<Hit>)],
+    skipped: usize,
+    errors: &[String],
+    stats: Option<&Report>,
+) -> io::Result<()> {
+    let matches: Vec<_> = files
+        .iter()
+        .flat_map(|(path, hits)| hits.iter().map(move |hit| json_hit(path, hit)))
+        .collect();
+    let errors: Vec<_> = errors.iter().map(|e| json_string(e)).collect();
+    let stats = match stats {
+        Some(report) => format!(
+            ",\"files\":[{}],\"totals\":{}",
+            json_files(report),
+            json_totals(&report.totals(), report.duration)
+        ),
+        None => String::new(),
+    };
+    writeln!(
+        output,
+        "{{\"matches\":[{}],\"skipped\":{}{},\"errors\":[{}]}}",
+        matches.join(","),
+        skipped,
+        stats,
+        errors.join(",")
+    )
+}
+
+fn json_hit(path: &Path, hit: &Hit) -> String {
+    format!(
+        "{{\"path\":{},\"line\":{},\"column\":{},\"offset\":{},\"length\":{}}}",
+        json_string(&path.to_string_lossy()),
+        hit.line,
+        hit.column,
+        hit.pos,
+        hit.len
+    )
+}
+
+/// Writes the statistics of each file of a run, their totals and the files that could not be
+/// processed.
+pub fn write_stats<W: Write>(output: &mut W, format: Format, report: &Report) -> io::Result<()> {
+    match format {
+        Format::Text => write_stats_text(output, report),
+        Format::Json => write_stats_json(output, report),
+    }
+}
+
+fn write_stats_text<W: Write>(output: &mut W, report: &Report) -> io::Result<()> {
+    for file in &report.files {
+        let path = file.path.display();
+        match file.skipped {
+            Some(reason) => writeln!(output, "{path}: skipped ({})", reason.as_str())?,
+            None => writeln!(
+                output,
+                "{path}: {} matches, {} replaced, {} bytes read, {} bytes written, {}, {}",
+                file.counts.found,
+                file.nb_kept(),
+                file.stats.bytes_read,
+                file.stats.bytes_written,
+                if file.stats.changed {
+                    "changed"
+                } else {
+                    "unchanged"
+                },
+                text_duration(file.stats.duration)
+            )?,
+        }
+    }
+    let totals = report.totals();
+    writeln!(
+        output,
+        "total: {} files, {} changed, {} skipped, {} matches, {} replaced, {} bytes read, {} bytes written, {}, {} errors",
+        totals.files,
+        totals.files_changed,
+        totals.files_skipped,
+        totals.matches,
+        totals.replacements,
+        totals.bytes_read,
+        totals.bytes_written,
+        text_duration(report.duration),
+        report.errors.len()
+    )
+}
+
+fn text_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn write_stats_json<W: Write>(output: &mut W, report: &Report) -> io::Result<()> {
+    let errors: Vec<_> = report
+        .errors
+        .iter()
+        .map(|e| json_string(&e.to_string()))
+        .collect();
+    writeln!(
+        output,
+        "{{\"files\":[{}],\"totals\":{},\"errors\":[{}]}}",
+        json_files(report),
+        json_totals(&report.totals(), report.duration),
+        errors.join(",")
+    )
+}
+
+fn json_files(report: &Report) -> String {
+    let files: Vec<_> = report.files.iter().map(json_file).collect();
+    files.join(",")
+}
+
+fn json_file(file: &FileReport) -> String {
+    let skipped = match file.skipped {
+        Some(reason) => json_string(reason.as_str()),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"path\":{},\"bytes_read\":{},\"bytes_written\":{},\"matches\":{},\"replacements\":{},\"duration_us\":{},\"changed\":{},\"skipped\":{}}}",
+        json_string(&file.path.to_string_lossy()),
+        file.stats.bytes_read,
+        file.stats.bytes_written,
+        file.counts.found,
+        file.nb_kept(),
+        file.stats.duration.as_micros(),
+        file.stats.changed,
+        skipped
+    )
+}
+
+fn json_totals(totals: &Totals, duration: Duration) -> String {
+    format!(
+        "{{\"files\":{},\"files_changed\":{},\"files_skipped\":{},\"bytes_read\":{},\"bytes_written\":{},\"matches\":{},\"replacements\":{},\"duration_us\":{}}}",
+        totals.files,
+        totals.files_changed,
+        totals.files_skipped,
+        totals.bytes_read,
+        totals.bytes_written,
+        totals.matches,
+        totals.replacements,
+        duration.as_micros()
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ved::{Counts, FileStats, SkipReason};
+
+    fn files() -> Vec<(PathBuf, Vec<Hit>)> {
+        vec![
+            (
+                PathBuf::from("a.rs"),
+                vec![Hit {
+                    pos: 6,
+                    len: 4,
+                    line: 2,
+                    column: 1,
+                }],
+            ),
+            (PathBuf::from("b.rs"), vec![]),
+        ]
+    }
+
+    fn written(format: Format, errors: &[String]) -> String {
+        let mut output = Vec::new();
+        assert!(write_check(&mut output, format, &files(), 1, errors, None).is_ok());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_check_text() {
+        assert_eq!(written(Format::Text, &[]), "a.rs:2:1\n");
+    }
+
+    #[test]
+    fn test_check_json() {
+        let errors = vec!["IO error: \"x\"".to_string()];
+        let expected = "{\"matches\":[{\"path\":\"a.rs\",\"line\":2,\"column\":1,\"offset\":6,\"length\":4}],\"skipped\":1,\"errors\":[\"IO error: \\\"x\\\"\"]}\n";
+        assert_eq!(written(Format::Json, &errors), expected);
+    }
+
+    fn report() -> Report {
+        let file = |path: &str, found, changed| FileReport {
+            path: PathBuf::from(path),
+            counts: Counts { found, skipped: 0 },
+            stats: FileStats {
+                bytes_read: 10,
+                bytes_written: if changed { 12 } else { 0 },
+                duration: Duration::from_micros(1500),
+                changed,
+            },
+            ..FileReport::default()
+        };
+        let skipped = FileReport {
+            skipped: Some(SkipReason::Quit),
+            ..file("c.rs", 0, false)
+        };
+        Report {
+            files: vec![file("a.rs", 2, true), file("b.rs", 0, false), skipped],
+            errors: vec![ved::Error::NothingToDo],
+            duration: Duration::from_millis(2),
+        }
+    }
+
+    fn written_stats(format: Format) -> String {
+        let mut output = Vec::new();
+        assert!(write_stats(&mut output, format, &report()).is_ok());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_stats_text() {
+        let expected = "a.rs: 2 matches, 2 replaced, 10 bytes read, 12 bytes written, changed, 1.500 ms\n\
+            b.rs: 0 matches, 0 replaced, 10 bytes read, 0 bytes written, unchanged, 1.500 ms\n\
+            c.rs: skipped (quit)\n\
+            total: 3 files, 1 changed, 1 skipped, 2 matches, 2 replaced, 30 bytes read, 12 bytes written, 2.000 ms, 1 errors\n";
+        assert_eq!(written_stats(Format::Text), expected);
+    }
+
+    #[test]
+    fn test_stats_json() {
+        let file = |path, matches, written, changed, skipped| {
+            format!("{{\"path\":\"{path}\",\"bytes_read\":10,\"bytes_written\":{written},\"matches\":{matches},\"replacements\":{matches},\"duration_us\":1500,\"changed\":{changed},\"skipped\":{skipped}}}")
+        };
+        let expected = format!(
+            "{{\"files\":[{},{},{}],\"totals\":{{\"files\":3,\"files_changed\":1,\"files_skipped\":1,\"bytes_read\":30,\"bytes_written\":12,\"matches\":2,\"replacements\":2,\"duration_us\":2000}},\"errors\":[\"nothing to search or edit\"]}}\n",
+            file("a.rs", 2, 12, true, "null"),
+            file("b.rs", 0, 0, false, "null"),
+            file("c.rs", 0, 0, false, "\"quit\""),
+        );
+        assert_eq!(written_stats(Format::Json), expected);
+    }
+
+    #[test]
+    fn test_check_with_stats() {
+        let check = |format| {
+            let mut output = Vec::new();
+            let report = report();
+            assert!(write_check(&mut output, format, &files(), 0, &[], Some(&report)).is_ok());
+            String::from_utf8(output).unwrap()
+        };
+        let text = check(Format::Text);
+        assert!(text.starts_with("a.rs:2:1\na.rs: 2 matches"), "{text}");
+        // the statistics are part of the only JSON object written
+        let json = check(Format::Json);
+        assert_eq!(json.lines().count(), 1, "{json}");
+        assert!(
+            json.starts_with("{\"matches\":[{\"path\":\"a.rs\""),
+            "{json}"
+        );
+        assert!(json.contains(",\"skipped\":0,\"files\":[{\"path\":\"a.rs\",\"bytes_read\":10,"));
+        assert!(
+            json.ends_with("\"duration_us\":2000},\"errors\":[]}\n"),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\\b\n\u{1}"), "\"a\\\\b\\n\\u0001\"");
+    }
+}
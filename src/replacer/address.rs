@@ -0,0 +1,372 @@
+use crate::replacer::error::Result;
+use crate::replacer::separator::{Separator, SeparatorFinder};
+use std::io::BufRead;
+use std::str::FromStr;
+
+/// One end of an Address.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Bound {
+    /// A line number, starting at 1
+    Line(usize),
+    /// Any line containing this text
+    Marker(String),
+}
+
+/// A region of lines, like the addresses of sed.
+///
+/// A region starts on the first line matching `start` and ends on the next line matching `end`.
+/// The end marker is only looked for after the start line, and a region delimited by markers can
+/// start again after it ended. A region that is never closed extends to the end of the stream.
+/// A line number `end` lower than or equal to the start line restricts the region to that line.
+/// Without `end`, the address is made of the lines matching `start`, each on its own.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Address {
+    pub start: Bound,
+    pub end: Option<Bound>,
+    /// Whether the lines of the bounds belong to the region
+    pub inclusive: bool,
+}
+
+impl FromStr for Bound {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        if let Some(marker) = s.strip_prefix('/').and_then(|m| m.strip_suffix('/')) {
+            if marker.is_empty() {
+                return Err("empty marker".to_string());
+            }
+            return Ok(Bound::Marker(marker.to_string()));
+        }
+        match s.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid address bound: {s:?}")),
+            Ok(n) => Ok(Bound::Line(n)),
+        }
+    }
+}
+
+/// Parses `BOUND` or `BOUND,BOUND`, where a bound is a line number or a `/marker/`.
+///
+/// The address is inclusive. A lone bound selects the lines it matches.
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        // A marker may contain commas, so it is delimited by its slashes first
+        let start_len = match s.strip_prefix('/').and_then(|m| m.find('/')) {
+            Some(marker_len) => marker_len + 2,
+            None => s.find(',').unwrap_or(s.len()),
+        };
+        let (start, rest) = s.split_at(start_len);
+        let end = match rest.strip_prefix(',') {
+            Some(end) => Some(end.parse()?),
+            None if rest.is_empty() => None,
+            None => return Err(format!("invalid address: {s:?}")),
+        };
+        Ok(Self {
+            start: start.parse()?,
+            end,
+            inclusive: true,
+        })
+    }
+}
+
+/// Tells which lines of a stream are inside a set of Addresses.
+///
+/// The stream is read line by line, so markers are found in lines of any size; a line is only
+/// read to its end when a position in it is requested.
+pub struct Regions<'a, R>
+where
+    R: BufRead,
+{
+    reader: R,
+    addresses: &'a [Address],
+    active: Vec<bool>,
+    /// Whether the start and end markers of each address were found in the current line
+    found: Vec<[bool; 2]>,
+    /// The end of the current line read so far, to find the markers that span two reads
+    carry: Vec<u8>,
+    separators: SeparatorFinder,
+    pos: usize,
+    line: usize,
+    /// The end offset, number and status of the last complete line
+    last: Option<(usize, usize, bool)>,
+}
+
+impl<'a, R> Regions<'a, R>
+where
+    R: BufRead,
+{
+    /// Creates the regions of `addresses` in `reader`, whose lines are ended by `separator`.
+    pub fn new(reader: R, addresses: &'a [Address], separator: Separator) -> Self {
+        Self {
+            reader,
+            addresses,
+            active: vec![false; addresses.len()],
+            found: vec![[false; 2]; addresses.len()],
+            carry: Vec::new(),
+            separators: SeparatorFinder::new(separator),
+            pos: 0,
+            line: 1,
+            last: None,
+        }
+    }
+
+    /// Returns the number of the line containing `pos`, and whether that line is in a region.
+    ///
+    /// Without any address, every line is in a region.
+    /// `pos` must not be lower than the one of the previous call.
+    pub fn locate(&mut self, pos: usize) -> Result<(usize, bool)> {
+        loop {
+            if let Some((end, line, status)) = self.last {
+                if pos <= end {
+                    return Ok((line, status));
+                }
+            }
+            if !self.read_line()? {
+                return Ok((self.line, false));
+            }
+        }
+    }
+
+    /// Reads up to the end of the current line and computes its status.
+    ///
+    /// Returns false if the stream was already over.
+    fn read_line(&mut self) -> Result<bool> {
+        let line_start = self.pos;
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                if self.pos == line_start {
+                    return Ok(false);
+                }
+                self.complete_line(self.pos);
+                return Ok(true);
+            }
+            let end = self.separators.find_end(buf);
+            let consumed = end.unwrap_or(buf.len());
+            let segment = buf[..consumed].to_owned();
+            if end.is_none() {
+                self.separators.skip(&segment);
+            }
+            self.find_markers(&segment);
+            self.reader.consume(consumed);
+            self.pos += consumed;
+            if end.is_some() {
+                self.complete_line(self.pos - 1);
+                return Ok(true);
+            }
+        }
+    }
+
+    fn find_markers(&mut self, segment: &[u8]) {
+        let mut window = std::mem::take(&mut self.carry);
+        window.extend_from_slice(segment);
+        let mut longest = 0;
+        for (address, found) in self.addresses.iter().zip(self.found.iter_mut()) {
+            for (bound, found) in [Some(&address.start), address.end.as_ref()]
+                .into_iter()
+                .zip(found)
+            {
+                if let Some(Bound::Marker(marker)) = bound {
+                    let marker = marker.as_bytes();
+                    longest = longest.max(marker.len());
+                    *found = *found || window.windows(marker.len()).any(|w| w == marker);
+                }
+            }
+        }
+        let keep = longest.saturating_sub(1).min(window.len());
+        self.carry = window.split_off(window.len() - keep);
+    }
+
+    fn complete_line(&mut self, end: usize) {
+        let mut status = self.addresses.is_empty();
+        for i in 0..self.addresses.len() {
+            status |= self.update_address(i);
+        }
+        self.last = Some((end, self.line, status));
+        self.line += 1;
+        self.carry.clear();
+        self.found.iter_mut().for_each(|f| *f = [false, false]);
+    }
+
+    /// Updates the state of an address with the current line, returns whether it contains it.
+    fn update_address(&mut self, i: usize) -> bool {
+        let address = &self.addresses[i];
+        let [found_start, found_end] = self.found[i];
+        if !self.active[i] {
+            let starts = match address.start {
+                Bound::Line(n) => n == self.line,
+                Bound::Marker(_) => found_start,
+            };
+            if !starts {
+                return false;
+            }
+            self.active[i] = match address.end {
+                None => false,
+                Some(Bound::Line(n)) => n > self.line,
+                Some(Bound::Marker(_)) => true,
+            };
+            address.inclusive
+        } else {
+            let ends = match address.end {
+                Some(Bound::Line(n)) => n == self.line,
+                Some(Bound::Marker(_)) => found_end,
+                None => true,
+            };
+            if !ends {
+                return true;
+            }
+            self.active[i] = false;
+            address.inclusive
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    impl Address {
+        pub fn lines(first: usize, last: usize) -> Self {
+            Self {
+                start: Bound::Line(first),
+                end: Some(Bound::Line(last)),
+                inclusive: true,
+            }
+        }
+
+        pub fn markers(start: &str, end: &str, inclusive: bool) -> Self {
+            Self {
+                start: Bound::Marker(start.to_owned()),
+                end: Some(Bound::Marker(end.to_owned())),
+                inclusive,
+            }
+        }
+    }
+
+    const CONTENT: &str = "l1\n// BEGIN\nl3\nl4 // END\nl5\n// BEGIN\nl7";
+
+    /// Returns the numbers of the lines of CONTENT that are in the regions.
+    fn lines_in(addresses: &[Address]) -> Vec<usize> {
+        let mut regions = Regions::new(Cursor::new(CONTENT), addresses, Separator::default());
+        let mut result = Vec::new();
+        let mut line_start = 0;
+        for line in CONTENT.split('\n') {
+            let (number, status) = regions.locate(line_start).unwrap();
+            if status {
+                result.push(number);
+            }
+            line_start += line.len() + 1;
+        }
+        result
+    }
+
+    #[test]
+    fn test_no_address() {
+        assert_eq!(lines_in(&[]), vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(lines_in(&[Address::lines(2, 4)]), vec![2, 3, 4]);
+        assert_eq!(lines_in(&["3".parse().unwrap()]), vec![3]);
+    }
+
+    #[test]
+    fn test_lines_end_before_start() {
+        assert_eq!(lines_in(&[Address::lines(5, 2)]), vec![5]);
+    }
+
+    #[test]
+    fn test_markers_inclusive() {
+        let address = Address::markers("BEGIN", "END", true);
+        assert_eq!(lines_in(&[address]), vec![2, 3, 4, 6, 7]);
+    }
+
+    #[test]
+    fn test_markers_exclusive() {
+        let address = Address::markers("BEGIN", "END", false);
+        assert_eq!(lines_in(&[address]), vec![3, 7]);
+    }
+
+    #[test]
+    fn test_marker_to_line() {
+        let address: Address = "/BEGIN/,3".parse().unwrap();
+        assert_eq!(lines_in(&[address]), vec![2, 3, 6]);
+    }
+
+    #[test]
+    fn test_lone_marker() {
+        let address: Address = "/BEGIN/".parse().unwrap();
+        assert_eq!(lines_in(&[address]), vec![2, 6]);
+        let address: Address = "/l/".parse().unwrap();
+        assert_eq!(lines_in(&[address]), vec![1, 3, 4, 5, 7]);
+    }
+
+    #[test]
+    fn test_union() {
+        let addresses = vec![Address::lines(1, 1), Address::lines(5, 5)];
+        assert_eq!(lines_in(&addresses), vec![1, 5]);
+    }
+
+    #[test]
+    fn test_marker_after_position_on_same_line() {
+        let mut regions = Regions::new(
+            Cursor::new("abba // BEGIN\nabba"),
+            &[],
+            Separator::default(),
+        );
+        assert_eq!(regions.locate(0).unwrap(), (1, true));
+        let addresses = vec![Address::markers("BEGIN", "END", true)];
+        let mut regions = Regions::new(
+            Cursor::new("abba // BEGIN\nabba"),
+            &addresses,
+            Separator::default(),
+        );
+        assert_eq!(regions.locate(0).unwrap(), (1, true));
+        assert_eq!(regions.locate(14).unwrap(), (2, true));
+    }
+
+    #[test]
+    fn test_marker_across_reads() {
+        let content = "xxxxBEGINxxxx\nabba";
+        let addresses = vec![Address::markers("BEGIN", "END", false)];
+        let reader = BufReader::with_capacity(3, Cursor::new(content));
+        let mut regions = Regions::new(reader, &addresses, Separator::default());
+        assert_eq!(regions.locate(0).unwrap(), (1, false));
+        assert_eq!(regions.locate(14).unwrap(), (2, true));
+    }
+
+    #[test]
+    fn test_separator_across_reads() {
+        let content = "l1\r\nBEGIN\r\nl3\nl3\r\nEND\r\nl5";
+        let addresses = vec![Address::markers("BEGIN", "END", true)];
+        let reader = BufReader::with_capacity(3, Cursor::new(content));
+        let mut regions = Regions::new(reader, &addresses, "crlf".parse().unwrap());
+        assert_eq!(regions.locate(0).unwrap(), (1, false));
+        assert_eq!(regions.locate(3).unwrap(), (1, false));
+        assert_eq!(regions.locate(4).unwrap(), (2, true));
+        assert_eq!(regions.locate(13).unwrap(), (3, true));
+        assert_eq!(regions.locate(20).unwrap(), (4, true));
+        assert_eq!(regions.locate(25).unwrap(), (5, false));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("10,20".parse(), Ok(Address::lines(10, 20)));
+        assert_eq!(
+            "/BEGIN/,/END/".parse(),
+            Ok(Address::markers("BEGIN", "END", true))
+        );
+        assert!("0".parse::<Address>().is_err());
+        assert!("//,3".parse::<Address>().is_err());
+        assert!("abc".parse::<Address>().is_err());
+        let lone = Address {
+            start: Bound::Marker("a,b".to_string()),
+            end: None,
+            inclusive: true,
+        };
+        assert_eq!("/a,b/".parse(), Ok(lone));
+    }
+}
@@ -1,53 +1,176 @@
 use super::diffheap::DiffHeap;
+use crate::replacer::columns::{Align, ColumnMode};
 use crate::replacer::diff::Diff;
-use crate::replacer::error::Result;
+use crate::replacer::error::{Error, Result};
+use crate::replacer::rows::RowRule;
+use crate::replacer::separator::Separator;
+use std::borrow::Cow;
 use std::io::Read;
-use std::iter::Zip;
-use std::slice;
+use std::num::NonZeroUsize;
 
-/// The maximum number of bytes between the start and the end of match.
+/// The default largest size of the search window.
+///
+/// A match must fit in the window, and so must an occurrence of a block pattern, from its first
+/// row to the end of its last row. Block occurrences that do not fit are not found.
 pub const SEARCH_MAX: usize = 4096 * 1024;
 
-/// Block patterns are guaranteed to match if they exist within the start of a line and this column.
-///
-/// However, depending on the chaotic refill mechanism, it is possible to get a match beyond this
-/// limit regardless.
-pub const COLUMN_MAX: usize = 1000;
+/// The size of the search window when a search starts, it grows as longer matches are tried.
+const SEARCH_INITIAL: usize = 64 * 1024;
+
+/// How a BufSearcher matches block patterns.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SearchConfig {
+    /// How the column at which the rows of a block must start is counted
+    pub columns: ColumnMode,
+    /// The distance between two tab stops, a tab takes a single column without it
+    pub tabstop: Option<NonZeroUsize>,
+    /// What ends the lines, the rows of a block are on consecutive lines
+    pub separator: Separator,
+    /// The rules of the rows of a block, the rows without one are matched as they are
+    pub rows: Vec<RowRule>,
+    /// Which column the rows of a block share
+    pub align: Align,
+    /// The last column at which the rows of a block may be aligned, there is no limit without it
+    ///
+    /// Without a limit, the occurrences are found at any column, as long as they fit in the
+    /// search window.
+    pub max_column: Option<usize>,
+    /// The largest size of the search window, in bytes, SEARCH_MAX without it
+    pub window: Option<NonZeroUsize>,
+    /// Whether the replacements keep the text after them at the same column, by padding them
+    /// with spaces or by removing the spaces that follow them
+    pub keep_columns: bool,
+}
+
+impl SearchConfig {
+    /// Returns whether the columns must be counted while replacing.
+    pub fn tracks_columns(&self) -> bool {
+        self.tabstop.is_some() || self.keep_columns
+    }
+
+    /// Returns the largest size of the search window.
+    pub fn window(&self) -> usize {
+        self.window.map_or(SEARCH_MAX, NonZeroUsize::get)
+    }
+
+    /// Returns the column following the byte at the start of `bytes`, when that byte is at
+    /// `column`.
+    pub fn next_column(&self, column: usize, bytes: &[u8]) -> usize {
+        match (bytes[0], self.tabstop) {
+            (b'\t', Some(tabstop)) => (column / tabstop + 1) * tabstop.get(),
+            _ => column + self.columns.width(bytes),
+        }
+    }
+
+    /// Returns the rule of a row of the block.
+    pub fn row_rule(&self, row: usize) -> RowRule {
+        self.rows.get(row).copied().unwrap_or_default()
+    }
+
+    /// Returns the first row of a block, starting at 1, that matches any line but is given a
+    /// replacement: nothing is ever replaced in such a row.
+    pub fn replaced_any_row<R: AsRef<[u8]>>(&self, replacements: &[R]) -> Option<usize> {
+        let replaced = |(row, replacement): &(usize, &R)| {
+            *row > 0 && self.row_rule(*row).any && !replacement.as_ref().is_empty()
+        };
+        replacements
+            .iter()
+            .enumerate()
+            .find(replaced)
+            .map(|(row, _)| row + 1)
+    }
+
+    /// Returns the column reached after `bytes`, when they start at `column`.
+    pub fn advance(&self, column: usize, bytes: &[u8]) -> usize {
+        self.advance_until(column, bytes, bytes.len()).0
+    }
+
+    /// Like `advance`, but stops once `limit` bytes were counted: returns the column reached and
+    /// the number of bytes counted, a separator going past `limit` being counted as a whole.
+    ///
+    /// The bytes after `limit` are only looked at to tell the characters and the separators that
+    /// start before it.
+    pub fn advance_until(&self, mut column: usize, bytes: &[u8], limit: usize) -> (usize, usize) {
+        let mut index = 0;
+        while index < limit {
+            if self.separator.starts(&bytes[index..]) {
+                column = 0;
+                index += self.separator.len();
+            } else {
+                column = self.next_column(column, &bytes[index..]);
+                index += 1;
+            }
+        }
+        (column, index)
+    }
+}
 
 pub struct BufSearcher<'search, R>
 where
     R: std::io::Read,
 {
-    patterns: &'search Vec<&'search str>,
-    replacements: &'search Vec<&'search str>,
+    patterns: Vec<&'search [u8]>,
+    replacements: Vec<&'search [u8]>,
+    config: SearchConfig,
     pos: usize,
-    reader: &'search mut R,
-    buf: Box<[u8; SEARCH_MAX]>,
+    reader: R,
+    /// The search window, it grows up to the window size of the config
+    buf: Vec<u8>,
     read_head: usize,
     drop_head: usize,
+    /// The column of the drop head, counted according to the column mode
     last_line_start: usize,
+    /// The number of bytes of a separator that remain to be dropped
+    separator_left: usize,
+    /// The index of the separator ending the line of the drop head, once it was found
+    line_end: Option<usize>,
+    /// Whether the reader reached the end of its stream
+    eof: bool,
     ready: DiffHeap<'search>,
+    /// The bytes of the stream, as start and end offsets, replaced by the rows of the occurrences
+    /// found that go on after the drop head: the next occurrences must not overlap them
+    emitted: Vec<(usize, usize)>,
 }
 
+/// Why the patterns were not matched at the drop head.
+enum Miss {
+    /// They do not match there
+    NoMatch,
+    /// The end of the data read so far was reached before knowing whether they match
+    NeedMore,
+}
+
+type Attempt<T> = std::result::Result<T, Miss>;
+
 impl<'search, R> BufSearcher<'search, R>
 where
     R: std::io::Read,
 {
-    pub fn new(
-        patterns: &'search Vec<&'search str>,
-        replacements: &'search Vec<&'search str>,
-        reader: &'search mut R,
-    ) -> Self {
+    pub fn new<P, Q>(
+        patterns: &'search [P],
+        replacements: &'search [Q],
+        config: SearchConfig,
+        reader: R,
+    ) -> Self
+    where
+        P: AsRef<[u8]>,
+        Q: AsRef<[u8]>,
+    {
         Self {
-            patterns,
-            replacements,
+            patterns: patterns.iter().map(AsRef::as_ref).collect(),
+            replacements: replacements.iter().map(AsRef::as_ref).collect(),
+            config,
             pos: 0,
             reader,
-            buf: vec![0; SEARCH_MAX].into_boxed_slice().try_into().unwrap(),
+            buf: Vec::new(),
             read_head: 0,
             drop_head: 0,
             last_line_start: 0,
+            separator_left: 0,
+            line_end: None,
+            eof: false,
             ready: DiffHeap::new(),
+            emitted: Vec::new(),
         }
     }
 
@@ -65,137 +188,373 @@ where
         }
     }
 
+    /// Moves the drop head to the next occurrence of the patterns.
+    ///
+    /// Whether an occurrence is found does not depend on how the reader splits the stream: when
+    /// the data read so far is not enough to tell, more is read and the occurrence is tried again.
     fn read_diffs(self: &mut Self) -> Result<Option<DiffHeap<'search>>> {
+        let window = self.config.window();
+        if self.minimum_match_length() > window {
+            return Err(Error::WindowTooSmall {
+                pattern: self.minimum_match_length(),
+                window,
+            });
+        }
         loop {
-            self.fill_buffer()?;
+            let head = self.pos + self.drop_head;
+            self.emitted.retain(|(_, end)| *end > head);
+            self.fill_buffer(self.minimum_match_length() + self.config.separator.len())?;
             let remaining_bytes = self.read_head - self.drop_head;
             if self.minimum_match_length() > remaining_bytes {
                 // End of file
                 break Ok(None);
             }
             match self.match_buffer() {
-                None => {
-                    self.drop(1);
-                }
-                Some(diff_heap) => {
+                Ok(diff_heap) => {
                     self.drop(self.patterns[0].len());
                     break Ok(Some(diff_heap));
                 }
+                Err(Miss::NoMatch) => self.drop(1),
+                Err(Miss::NeedMore) => {
+                    // Asking for twice as much keeps the number of attempts logarithmic; the
+                    // occurrence cannot fit in the search window if nothing more can be read
+                    let eof = self.eof;
+                    self.fill_buffer(2 * remaining_bytes + 1)?;
+                    if self.read_head - self.drop_head == remaining_bytes && self.eof == eof {
+                        self.drop(1)
+                    }
+                }
             };
         }
     }
 
     fn drop(self: &mut Self, nb_drop: usize) {
         for _ in 0..nb_drop {
-            if self.buf[self.drop_head] == '\n' as u8 {
-                self.last_line_start = 0
+            if self.separator_left > 0 {
+                self.separator_left -= 1;
+            } else if self.is_separator(self.drop_head) {
+                self.last_line_start = 0;
+                self.separator_left = self.config.separator.len() - 1;
+                self.line_end = None;
             } else {
-                self.last_line_start += 1
+                self.last_line_start = self.next_column(self.last_line_start, self.drop_head)
             }
             self.drop_head += 1;
         }
     }
 
     fn minimum_match_length(self: &Self) -> usize {
-        let pattern_sum: usize = self.patterns.iter().map(|p| p.len()).sum();
-        let newlines = self.patterns.len() - 1;
-        pattern_sum + newlines
-    }
-
-    /// Returns the largest number of bytes that a match could span.
-    ///
-    /// Note that because of vertical matching, there's not really a maximum length, as the match
-    /// could start at an arbitrary column.
-    /// This the reason why there's a COLUMN_MAX value (this limits the maximum span of a match).
-    fn maximum_match_length(self: &Self) -> usize {
-        let pattern_sum: usize = self.patterns.iter().map(|p| p.len()).sum();
-        let newlines = (self.patterns.len() - 1) * COLUMN_MAX;
-        pattern_sum + newlines
+        let mut length = self.patterns[0].len();
+        for (row, pattern) in self.patterns.iter().enumerate().skip(1) {
+            let rule = self.config.row_rule(row);
+            if !rule.optional {
+                length += self.config.separator.len();
+                if !rule.any {
+                    length += pattern.len();
+                }
+            }
+        }
+        length
     }
 
-    fn fill_buffer(self: &mut Self) -> Result<()> {
-        let remaining_bytes = self.read_head - self.drop_head;
-        if self.maximum_match_length() > remaining_bytes {
-            let missing_bytes = self.maximum_match_length() - remaining_bytes;
-            if self.read_head + missing_bytes >= SEARCH_MAX {
-                self.compress_buffer();
+    /// Reads until `wanted` bytes are available after the drop head, the end of the stream is
+    /// reached or the search window is full.
+    fn fill_buffer(self: &mut Self, wanted: usize) -> Result<()> {
+        while self.read_head - self.drop_head < wanted && !self.eof {
+            if self.read_head == self.buf.len() {
+                self.make_room();
+                if self.read_head == self.buf.len() {
+                    break;
+                }
             }
             let nb_read = self.reader.read(&mut self.buf[self.read_head..])?;
+            self.eof = nb_read == 0;
             self.read_head += nb_read;
         }
         Ok(())
     }
 
+    /// Makes room after the read head, by growing the buffer when the bytes to keep take more
+    /// than half of it, and by moving them to its start.
+    fn make_room(self: &mut Self) {
+        let remaining_bytes = self.read_head - self.drop_head;
+        let window = self.config.window();
+        if remaining_bytes >= self.buf.len() / 2 && self.buf.len() < window {
+            let size = (self.buf.len() * 2).clamp(SEARCH_INITIAL.min(window), window);
+            self.buf.resize(size, 0);
+        }
+        if self.drop_head > 0 {
+            self.compress_buffer();
+        }
+    }
+
     fn compress_buffer(self: &mut Self) {
         let remaining_bytes = self.read_head - self.drop_head;
-        let tmp = self.buf[self.drop_head..self.read_head].to_owned();
-        self.buf[..remaining_bytes].clone_from_slice(&tmp);
+        self.buf.copy_within(self.drop_head..self.read_head, 0);
+        self.line_end = self.line_end.map(|end| end - self.drop_head);
         self.pos += self.drop_head;
         self.drop_head = 0;
         self.read_head = remaining_bytes;
     }
 
-    /// TODO this really needs a refactor
-    fn match_buffer(self: &mut Self) -> Option<DiffHeap<'search>> {
-        let mut buf_offset = 0;
-        let first_match =
-            self.match_one_pattern(buf_offset, self.patterns[0], self.replacements[0])?;
-        let mut previous_match_len = first_match.diff.remove;
-        let line_offset = first_match.line_offset;
+    /// Returns the miss of an attempt that reached the end of the data read so far.
+    fn end_of_data(&self) -> Miss {
+        if self.eof {
+            Miss::NoMatch
+        } else {
+            Miss::NeedMore
+        }
+    }
+
+    /// Matches every row of the patterns, starting with the first one at the drop head.
+    ///
+    /// Each row must start, or end, at the same column as the first one, on the line following
+    /// the previous row, unless the rule of the row says otherwise.
+    fn match_buffer(&mut self) -> Attempt<DiffHeap<'search>> {
+        let anchor = self.pos + self.drop_head;
+        let column = match self.config.align {
+            Align::Start => self.last_line_start,
+            Align::End => {
+                let first_row = self.patterns[0];
+                self.config.advance(self.last_line_start, first_row)
+            }
+        };
         let mut result = DiffHeap::new();
-        result.push(first_match.diff);
-
-        for (pattern, replacement) in self.get_replacement_pairs().skip(1) {
-            buf_offset += previous_match_len
-                + self.next_line_offset(self.drop_head + previous_match_len)?
-                + line_offset;
-            let mat = self.match_one_pattern(buf_offset, pattern, replacement)?;
-            previous_match_len = mat.diff.remove;
-            result.push(mat.diff);
+        let (pattern, replacement) = (self.patterns[0], self.replacements[0]);
+        let first = self.match_one_pattern(self.drop_head, pattern, replacement, anchor, 0)?;
+        if self.patterns.len() == 1 {
+            result.push(first);
+            return Ok(result);
+        }
+        if self.config.max_column.is_some_and(|max| column > max) {
+            return Err(Miss::NoMatch);
+        }
+        // where the line of the previous row is searched from
+        let mut row_end = self.drop_head + first.remove;
+        let mut emitted = vec![(first.pos, first.pos + first.remove)];
+        result.push(first);
+        // the end of the first line is looked for once for all the attempts on that line
+        let line_end = match self.line_end.filter(|end| *end >= row_end) {
+            Some(end) => end,
+            None => self.separator_from(row_end)?,
+        };
+        self.line_end = Some(line_end);
+
+        for row in 1..self.patterns.len() {
+            let line_start = if row == 1 {
+                line_end + self.config.separator.len()
+            } else {
+                self.next_line_start(row_end)?
+            };
+            match self.match_row(line_start, column, anchor, row) {
+                Ok(diff) => {
+                    row_end = diff.pos - self.pos + diff.remove;
+                    emitted.push((diff.pos, diff.pos + diff.remove));
+                    result.push(diff);
+                }
+                Err(Miss::NoMatch) if self.config.row_rule(row).optional => {
+                    result.push(self.placeholder(row_end, anchor, row))
+                }
+                Err(miss) => return Err(miss),
+            }
         }
-        Some(result)
+        self.emitted.extend(emitted);
+        Ok(result)
     }
 
-    fn get_replacement_pairs(
+    /// Matches a row from the line starting at `line_start`, skipping at most the gap of its
+    /// rule, and within the columns of its rule around `column`, the column of the first row.
+    fn match_row(
         &self,
-    ) -> Zip<slice::Iter<'search, &'search str>, slice::Iter<'search, &'search str>> {
-        self.patterns.into_iter().zip(self.replacements)
+        mut line_start: usize,
+        column: usize,
+        anchor: usize,
+        row: usize,
+    ) -> Attempt<Diff<'search>> {
+        let (pattern, replacement) = (self.patterns[row], self.replacements[row]);
+        let rule = self.config.row_rule(row);
+        for gap in 0..=rule.max_gap {
+            if gap > 0 {
+                line_start = self.next_line_start(line_start)?;
+            }
+            if rule.any {
+                return Ok(self.placeholder(line_start, anchor, row));
+            }
+            for delta in rule.min_delta..=rule.max_delta {
+                let column = match column.checked_add_signed(delta) {
+                    None => continue,
+                    Some(column) => column,
+                };
+                if self.config.max_column.is_some_and(|max| column > max) {
+                    continue;
+                }
+                let found = self
+                    .row_start(line_start, column, pattern)
+                    .and_then(|start| {
+                        self.match_one_pattern(start, pattern, replacement, anchor, row)
+                    });
+                match found {
+                    Err(Miss::NoMatch) => (),
+                    found => return found,
+                }
+            }
+        }
+        Err(Miss::NoMatch)
     }
 
-    /// Returns the number of bytes between an offset and the next newline.
+    /// Returns the index in the buffer where a row aligned on the given column of the line
+    /// starting at `line_start` would start.
+    fn row_start(&self, line_start: usize, column: usize, pattern: &[u8]) -> Attempt<usize> {
+        match self.config.align {
+            Align::Start => self.column_start(line_start, column),
+            Align::End => self
+                .column_end(line_start, column)?
+                .checked_sub(pattern.len())
+                .filter(|start| *start >= line_start)
+                .ok_or(Miss::NoMatch),
+        }
+    }
+
+    /// Returns a diff changing nothing, for a row of a block where nothing is replaced.
+    fn placeholder(&self, index: usize, anchor: usize, row: usize) -> Diff<'search> {
+        Diff {
+            pos: self.pos + index,
+            remove: 0,
+            add: Cow::Borrowed(&[]),
+            anchor,
+            row,
+        }
+    }
+
+    /// Returns the number of columns taken by the byte at `index` in the buffer.
+    fn column_width(&self, index: usize) -> usize {
+        self.config.columns.width(&self.buf[index..self.read_head])
+    }
+
+    /// Returns the column following the byte at `index` in the buffer, when it is at `column`.
+    fn next_column(&self, column: usize, index: usize) -> usize {
+        self.config
+            .next_column(column, &self.buf[index..self.read_head])
+    }
+
+    /// Returns whether a separator starts at `index` in the buffer.
+    fn is_separator(&self, index: usize) -> bool {
+        self.config
+            .separator
+            .starts(&self.buf[index..self.read_head])
+    }
+
+    /// Returns the index of the first separator from `index` in the buffer.
+    fn separator_from(&self, index: usize) -> Attempt<usize> {
+        match self.config.separator.find(&self.buf[index..self.read_head]) {
+            None => Err(self.end_of_data()),
+            Some(separator) => Ok(index + separator),
+        }
+    }
+
+    /// Returns the index of the start of the line following the one of `index` in the buffer.
+    fn next_line_start(&self, index: usize) -> Attempt<usize> {
+        Ok(self.separator_from(index)? + self.config.separator.len())
+    }
+
+    /// Returns the index in the buffer where a row starting at the given column of the line
+    /// starting at `line_start` would start.
     ///
-    /// What's returned is the offset of the character immediately following the newline character,
-    /// not the newline character itself.
-    fn next_line_offset(self: &Self, start_offset: usize) -> Option<usize> {
-        for i in start_offset..SEARCH_MAX {
-            if self.buf[i] == '\n' as u8 {
-                return Some(i - start_offset + 1);
+    /// There is none if the line is shorter, or if the column is in the middle of a wide
+    /// character or of an expanded tab.
+    fn column_start(&self, line_start: usize, column: usize) -> Attempt<usize> {
+        let mut index = self.column_index(line_start, column)?;
+        // skip the rest of the last character, and the characters that take no column
+        loop {
+            if index >= self.read_head {
+                return Err(self.end_of_data());
             }
+            if self.is_separator(index) || self.column_width(index) > 0 {
+                return Ok(index);
+            }
+            index += 1;
+        }
+    }
+
+    /// Returns the index in the buffer where a row ending at the given column of the line
+    /// starting at `line_start` would end.
+    fn column_end(&self, line_start: usize, column: usize) -> Attempt<usize> {
+        let mut index = self.column_index(line_start, column)?;
+        // skip the rest of the last character
+        loop {
+            if index >= self.read_head {
+                return match self.eof {
+                    true => Ok(index),
+                    false => Err(Miss::NeedMore),
+                };
+            }
+            if !self.config.columns.continues_char(self.buf[index]) {
+                return Ok(index);
+            }
+            index += 1;
         }
-        None
     }
 
+    /// Returns the index in the buffer of the byte following the one that reaches the given
+    /// column of the line starting at `line_start`.
+    fn column_index(&self, line_start: usize, column: usize) -> Attempt<usize> {
+        let mut index = line_start;
+        let mut current = 0;
+        while current < column {
+            if index >= self.read_head {
+                return Err(self.end_of_data());
+            }
+            if self.is_separator(index) {
+                return Err(Miss::NoMatch);
+            }
+            current = self.next_column(current, index);
+            index += 1;
+        }
+        if current != column {
+            return Err(Miss::NoMatch);
+        }
+        Ok(index)
+    }
+
+    /// Returns whether the bytes from `start` in the stream, `len` of them, overlap a row of an
+    /// occurrence already found.
+    fn overlaps_emitted(&self, start: usize, len: usize) -> bool {
+        let end = start + len;
+        self.emitted
+            .iter()
+            .any(|&(from, to)| start < to && from < end)
+    }
+
+    /// Matches a row at `slice_start` in the buffer, unless it would overlap a row of an
+    /// occurrence already found.
     fn match_one_pattern(
         self: &Self,
-        offset: usize,
-        pattern: &str,
-        replacement: &'search str,
-    ) -> Option<Match<'search>> {
-        let slice_start = self.drop_head + offset;
-        let slice_end = self.drop_head + offset + pattern.len();
+        slice_start: usize,
+        pattern: &[u8],
+        replacement: &'search [u8],
+        anchor: usize,
+        row: usize,
+    ) -> Attempt<Diff<'search>> {
+        let slice_end = slice_start + pattern.len();
+        if slice_end > self.read_head {
+            // the data read so far may already tell that the pattern does not match
+            let available = &self.buf[slice_start.min(self.read_head)..self.read_head];
+            if !pattern.starts_with(available) {
+                return Err(Miss::NoMatch);
+            }
+            return Err(self.end_of_data());
+        }
         let slice = &self.buf[slice_start..slice_end];
-        if slice == pattern.as_bytes() {
-            Some(Match {
-                diff: Diff {
-                    pos: self.pos + slice_start,
-                    remove: pattern.len(),
-                    add: replacement,
-                },
-                line_offset: self.last_line_start,
-            })
-        } else {
-            None
+        if slice != pattern || self.overlaps_emitted(self.pos + slice_start, pattern.len()) {
+            return Err(Miss::NoMatch);
         }
+        Ok(Diff {
+            pos: self.pos + slice_start,
+            remove: pattern.len(),
+            add: replacement.into(),
+            anchor,
+            row,
+        })
     }
 }
 
@@ -215,16 +574,10 @@ where
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-struct Match<'str> {
-    diff: Diff<'str>,
-    /// The offset of the diff with the start of the line
-    line_offset: usize,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::replacer::rows::NumberedRule;
     use std::iter;
     use stringreader::StringReader;
 
@@ -233,7 +586,12 @@ mod tests {
         let mut input = StringReader::new("abba");
         let patterns = vec!["abba"];
         let replacements = vec!["toto"];
-        let mut buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let mut buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let option = buf_searcher.next();
         assert!(option.is_some());
         let result = option.unwrap();
@@ -242,7 +600,9 @@ mod tests {
         let expected = Diff {
             pos: 0,
             remove: 4,
-            add: "toto",
+            add: "toto".as_bytes().into(),
+            anchor: 0,
+            row: 0,
         };
         assert_eq!(diff, expected);
     }
@@ -252,18 +612,27 @@ mod tests {
         let mut input = StringReader::new("abba has sold abba records");
         let patterns = vec!["abba"];
         let replacements = vec!["toto"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 0,
                 remove: 4,
-                add: "toto",
+                add: "toto".as_bytes().into(),
+                anchor: 0,
+                row: 0,
             },
             Diff {
                 pos: 14,
                 remove: 4,
-                add: "toto",
+                add: "toto".as_bytes().into(),
+                anchor: 14,
+                row: 0,
             },
         ];
         assert_eq!(diffs, expected);
@@ -274,51 +643,74 @@ mod tests {
         let mut input = StringReader::new("abba\ntoto");
         let patterns = vec!["abba", "toto"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 0,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 0,
+                row: 0,
             },
             Diff {
                 pos: 5,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 0,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
     }
 
     #[test]
-    fn test_block_with_line_offset() {
+    fn test_block_indented() {
         let mut input = StringReader::new("_abba\n_toto");
         let patterns = vec!["abba", "toto"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 1,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
             },
             Diff {
                 pos: 7,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
     }
 
     #[test]
-    fn test_block_wrong_line_offset() {
+    fn test_block_misaligned() {
         let mut input = StringReader::new("_abba\n__toto");
         let patterns = vec!["abba", "toto"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![];
         assert_eq!(diffs, expected);
@@ -329,18 +721,27 @@ mod tests {
         let mut input = StringReader::new("_who\n_abba");
         let patterns = vec!["who", "abba"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 1,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
             },
             Diff {
                 pos: 6,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
@@ -351,18 +752,27 @@ mod tests {
         let mut input = StringReader::new("_who\n_abba");
         let patterns = vec!["who", "abba"];
         let replacements = vec!["queen", "beatles"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 1,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
             },
             Diff {
                 pos: 6,
                 remove: 4,
-                add: "beatles",
+                add: "beatles".as_bytes().into(),
+                anchor: 1,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
@@ -373,28 +783,41 @@ mod tests {
         let mut input = StringReader::new("_who=+who\n_abba+abba");
         let patterns = vec!["who", "abba"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 1,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
             },
             Diff {
                 pos: 6,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 6,
+                row: 0,
             },
             Diff {
                 pos: 11,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 1,
             },
             Diff {
                 pos: 16,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 6,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
@@ -405,33 +828,466 @@ mod tests {
         let mut input = StringReader::new("_who\n_abba+who\n_====+abba");
         let patterns = vec!["who", "abba"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: 1,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
             },
             Diff {
                 pos: 6,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 1,
             },
             Diff {
                 pos: 11,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 11,
+                row: 0,
             },
             Diff {
                 pos: 21,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: 11,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
     }
 
+    #[test]
+    fn test_block_end_two_mixed_matches() {
+        let mut input = StringReader::new(" who\nabba+who\n====abba");
+        let patterns = vec!["who", "abba"];
+        let replacements = vec!["queen", "queen"];
+        let config = SearchConfig {
+            align: Align::End,
+            ..SearchConfig::default()
+        };
+        let buf_searcher = BufSearcher::new(&patterns, &replacements, config, &mut input);
+        let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
+        let expected = vec![
+            Diff {
+                pos: 1,
+                remove: 3,
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 0,
+            },
+            Diff {
+                pos: 5,
+                remove: 4,
+                add: "queen".as_bytes().into(),
+                anchor: 1,
+                row: 1,
+            },
+            Diff {
+                pos: 10,
+                remove: 3,
+                add: "queen".as_bytes().into(),
+                anchor: 10,
+                row: 0,
+            },
+            Diff {
+                pos: 18,
+                remove: 4,
+                add: "queen".as_bytes().into(),
+                anchor: 10,
+                row: 1,
+            },
+        ];
+        assert_eq!(diffs, expected);
+    }
+
+    #[test]
+    fn test_block_end_columns() {
+        let patterns = vec!["12", "3"];
+        let content = "  12\n  3\n  12\n   3";
+        assert_eq!(
+            block_diffs(content, &patterns, ColumnMode::Bytes),
+            vec![2, 7]
+        );
+        let config = SearchConfig {
+            align: Align::End,
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &patterns, config), vec![11, 17]);
+        // the rows end at the same character column
+        let content = "été 12\nabcé 3";
+        let config = SearchConfig {
+            align: Align::End,
+            columns: ColumnMode::Chars,
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &patterns, config), vec![6, 15]);
+        // a row does not start before its line
+        let content = "2\n3";
+        let config = SearchConfig {
+            align: Align::End,
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &vec!["2", "13"], config), vec![]);
+    }
+
+    fn block_diffs(content: &str, patterns: &Vec<&str>, columns: ColumnMode) -> Vec<usize> {
+        let config = SearchConfig {
+            columns,
+            ..SearchConfig::default()
+        };
+        config_diffs(content, patterns, config)
+    }
+
+    fn config_diffs(content: &str, patterns: &Vec<&str>, config: SearchConfig) -> Vec<usize> {
+        let mut input = StringReader::new(content);
+        let buf_searcher = BufSearcher::new(patterns, patterns, config, &mut input);
+        buf_searcher.map(|x| x.unwrap().pos).collect()
+    }
+
+    #[test]
+    fn test_block_three_rows() {
+        let patterns = vec!["a", "bb", "c"];
+        let diffs = block_diffs("_a\n_bb\n_c\n_a\n_bb\n__c", &patterns, ColumnMode::Bytes);
+        assert_eq!(diffs, vec![1, 4, 8]);
+    }
+
+    #[test]
+    fn test_block_columns_accents() {
+        let patterns = vec!["who", "abba"];
+        let content = "été who\nabc abba";
+        assert_eq!(block_diffs(content, &patterns, ColumnMode::Bytes), vec![]);
+        assert_eq!(
+            block_diffs(content, &patterns, ColumnMode::Chars),
+            vec![6, 14]
+        );
+    }
+
+    #[test]
+    fn test_block_columns_combining() {
+        let patterns = vec!["who", "abba"];
+        let content = "e\u{301}te\u{301} who\nabc abba";
+        assert_eq!(block_diffs(content, &patterns, ColumnMode::Chars), vec![]);
+        assert_eq!(
+            block_diffs(content, &patterns, ColumnMode::Width),
+            vec![8, 16]
+        );
+    }
+
+    #[test]
+    fn test_block_columns_wide() {
+        let patterns = vec!["who", "abba"];
+        let content = "日本 who\nabcd abba";
+        assert_eq!(block_diffs(content, &patterns, ColumnMode::Chars), vec![]);
+        assert_eq!(
+            block_diffs(content, &patterns, ColumnMode::Width),
+            vec![7, 16]
+        );
+        let content = "abcd who\nabc日abba";
+        assert_eq!(
+            block_diffs(content, &patterns, ColumnMode::Width),
+            vec![5, 15]
+        );
+        // a row cannot start in the middle of a wide character
+        let content = "abc who\nabc日abba";
+        assert_eq!(block_diffs(content, &patterns, ColumnMode::Width), vec![]);
+    }
+
+    #[test]
+    fn test_block_tabstop() {
+        let patterns = vec!["who", "abba"];
+        let content = "\twho\n        abba\n  \t  who\n\t  abba";
+        assert_eq!(block_diffs(content, &patterns, ColumnMode::Bytes), vec![]);
+        let config = SearchConfig {
+            tabstop: NonZeroUsize::new(8),
+            ..SearchConfig::default()
+        };
+        assert_eq!(
+            config_diffs(content, &patterns, config),
+            vec![1, 13, 23, 30]
+        );
+        let config = SearchConfig {
+            tabstop: NonZeroUsize::new(4),
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &patterns, config), vec![23, 30]);
+    }
+
+    fn separator_diffs(content: &str, patterns: &Vec<&str>, separator: &str) -> Vec<usize> {
+        let config = SearchConfig {
+            separator: separator.parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        config_diffs(content, patterns, config)
+    }
+
+    #[test]
+    fn test_block_separators() {
+        let patterns = vec!["who", "abba"];
+        let content = "_who\r\n_abba";
+        assert_eq!(separator_diffs(content, &patterns, "lf"), vec![1, 7]);
+        assert_eq!(separator_diffs(content, &patterns, "crlf"), vec![1, 7]);
+        assert_eq!(separator_diffs(content, &patterns, "cr"), vec![]);
+        let content = "_who\r_abba\r\n_who\n_abba";
+        assert_eq!(separator_diffs(content, &patterns, "lf"), vec![13, 18]);
+        assert_eq!(separator_diffs(content, &patterns, "crlf"), vec![]);
+        assert_eq!(separator_diffs(content, &patterns, "cr"), vec![1, 6]);
+
+        let content = "_who\0_abba\0";
+        let config = SearchConfig {
+            separator: "nul".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &patterns, config), vec![1, 6]);
+
+        let content = "_who;;_abba;;;_who;;_;abba";
+        let config = SearchConfig {
+            separator: ";;".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        assert_eq!(config_diffs(content, &patterns, config), vec![1, 7, 15, 22]);
+    }
+
+    fn rule_diffs(content: &str, patterns: &Vec<&str>, rules: &[&str]) -> Vec<(usize, usize)> {
+        let rules: Vec<_> = rules.iter().map(|r| r.parse().unwrap()).collect();
+        let config = SearchConfig {
+            rows: NumberedRule::collect(&rules),
+            ..SearchConfig::default()
+        };
+        let mut input = StringReader::new(content);
+        let buf_searcher = BufSearcher::new(patterns, patterns, config, &mut input);
+        buf_searcher
+            .map(|x| x.unwrap())
+            .filter(|d| !d.is_noop())
+            .map(|d| (d.pos, d.row))
+            .collect()
+    }
+
+    #[test]
+    fn test_block_any_row() {
+        let patterns = vec!["| a", "?", "| c"];
+        let content = "| a |\n| x |\n| c |\n| a |\n| c |";
+        assert_eq!(rule_diffs(content, &patterns, &[]), vec![]);
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:any"]),
+            vec![(0, 0), (12, 2)]
+        );
+    }
+
+    #[test]
+    fn test_replaced_any_row() {
+        let rules: Vec<NumberedRule> = vec!["1:any".parse().unwrap(), "3:any".parse().unwrap()];
+        let config = SearchConfig {
+            rows: NumberedRule::collect(&rules),
+            ..SearchConfig::default()
+        };
+        assert_eq!(config.replaced_any_row(&["A", "B", "", "D"]), None);
+        assert_eq!(config.replaced_any_row(&["A", "B", "C", "D"]), Some(3));
+        assert_eq!(SearchConfig::default().replaced_any_row(&["A", "B"]), None);
+    }
+
+    #[test]
+    fn test_block_gap() {
+        let patterns = vec!["a", "c"];
+        let content = "_a\n\n__\n_c\n_a\n_b\n_b\n_b\n_c";
+        assert_eq!(rule_diffs(content, &patterns, &[]), vec![]);
+        assert_eq!(rule_diffs(content, &patterns, &["2:gap=1"]), vec![]);
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:gap=2"]),
+            vec![(1, 0), (8, 1)]
+        );
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:gap=3"]),
+            vec![(1, 0), (8, 1), (11, 0), (23, 1)]
+        );
+    }
+
+    #[test]
+    fn test_block_gap_overlap() {
+        // the second "a" could end with the first "b", already replaced
+        let patterns = vec!["a", "b"];
+        let content = "a\na\nb\nb";
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:gap=2"]),
+            vec![(0, 0), (2, 0), (4, 1), (6, 1)]
+        );
+        assert_eq!(
+            rule_diffs("a\na\nb", &patterns, &["2:gap=2"]),
+            vec![(0, 0), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn test_block_optional_row() {
+        let patterns = vec!["a", "b", "c"];
+        let content = "_a\n_b\n_c\n_a\n_c\n_a\n_x\n_c";
+        assert_eq!(
+            rule_diffs(content, &patterns, &[]),
+            vec![(1, 0), (4, 1), (7, 2)]
+        );
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:optional"]),
+            vec![(1, 0), (4, 1), (7, 2), (10, 0), (13, 2)]
+        );
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:optional", "3:gap=1"]),
+            vec![(1, 0), (4, 1), (7, 2), (10, 0), (13, 2), (16, 0), (22, 2)]
+        );
+    }
+
+    #[test]
+    fn test_block_delta() {
+        let patterns = vec!["if", "body"];
+        let content = "    if\n        body\n  if\n    body";
+        assert_eq!(rule_diffs(content, &patterns, &[]), vec![]);
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:delta=+4"]),
+            vec![(4, 0), (15, 1)]
+        );
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:delta=2"]),
+            vec![(22, 0), (29, 1)]
+        );
+        let content = "  ab\nc";
+        assert_eq!(
+            rule_diffs(content, &vec!["ab", "c"], &["2:delta=-2"]),
+            vec![(2, 0), (5, 1)]
+        );
+    }
+
+    #[test]
+    fn test_block_delta_range() {
+        let patterns = vec!["12", "3"];
+        let content = " 12\n  3\n 12\n3\n 12\n   3";
+        assert_eq!(rule_diffs(content, &patterns, &[]), vec![]);
+        assert_eq!(
+            rule_diffs(content, &patterns, &["2:delta=-1..1"]),
+            vec![(1, 0), (6, 1), (9, 0), (12, 1)]
+        );
+    }
+
+    /// A reader returning at most `chunk` bytes at a time.
+    struct ChunkReader<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for ChunkReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn chunked_diffs(content: &str, patterns: &Vec<&str>, config: &SearchConfig) -> Vec<usize> {
+        let mut results = Vec::new();
+        for chunk in [1, 7, 4096, usize::MAX] {
+            let mut input = ChunkReader {
+                data: content.as_bytes(),
+                chunk,
+            };
+            let buf_searcher = BufSearcher::new(patterns, patterns, config.clone(), &mut input);
+            let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap().pos).collect();
+            results.push(diffs);
+        }
+        results.dedup();
+        assert_eq!(
+            results.len(),
+            1,
+            "the matches depend on the reads: {results:?}"
+        );
+        results.pop().unwrap()
+    }
+
+    #[test]
+    fn test_block_far_column() {
+        let patterns = vec!["who", "abba"];
+        let padding = "X".repeat(100_000);
+        let content = format!("{padding}who\n{padding}abba\n{padding}_who\n{padding}abba");
+        let config = SearchConfig::default();
+        assert_eq!(
+            chunked_diffs(&content, &patterns, &config),
+            vec![100_000, 200_004]
+        );
+    }
+
+    #[test]
+    fn test_block_max_column() {
+        let patterns = vec!["who", "abba"];
+        let content = "__who\n__abba\n___who\n___abba";
+        let mut config = SearchConfig {
+            max_column: Some(2),
+            ..SearchConfig::default()
+        };
+        assert_eq!(chunked_diffs(content, &patterns, &config), vec![2, 8]);
+        config.max_column = Some(3);
+        assert_eq!(
+            chunked_diffs(content, &patterns, &config),
+            vec![2, 8, 16, 23]
+        );
+        config.max_column = Some(1);
+        assert_eq!(chunked_diffs(content, &patterns, &config), vec![]);
+    }
+
+    #[test]
+    fn test_block_deterministic() {
+        let patterns = vec!["a", "bb", "c"];
+        let content = "_a\r\n_bb\r\n_c\r\n_a\r\n_bb\r\n__c\r\n_a\r\n_bb\r\n_c";
+        let config = SearchConfig {
+            separator: "crlf".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        assert_eq!(
+            chunked_diffs(content, &patterns, &config),
+            vec![1, 5, 10, 28, 32, 37]
+        );
+        let config = SearchConfig {
+            columns: ColumnMode::Width,
+            ..SearchConfig::default()
+        };
+        let content = "日a\n本bb\n語c";
+        assert_eq!(chunked_diffs(content, &patterns, &config), vec![3, 8, 14]);
+    }
+
+    #[test]
+    fn test_small_window() {
+        let patterns = vec!["who", "abba"];
+        let padding = "-".repeat(100);
+        let content = format!("{padding}\n who\n abba\n{padding}\nwho{padding}\nabba");
+        let config = SearchConfig {
+            window: NonZeroUsize::new(12),
+            ..SearchConfig::default()
+        };
+        // the second occurrence does not fit in the window
+        assert_eq!(chunked_diffs(&content, &patterns, &config), vec![102, 107]);
+        let config = SearchConfig {
+            window: NonZeroUsize::new(2),
+            ..SearchConfig::default()
+        };
+        let mut input = StringReader::new(&content);
+        let mut buf_searcher = BufSearcher::new(&patterns, &patterns, config, &mut input);
+        assert!(matches!(
+            buf_searcher.next(),
+            Some(Err(Error::WindowTooSmall {
+                pattern: 8,
+                window: 2
+            }))
+        ));
+    }
+
     #[test]
     fn test_block_max_context() {
         let garbage_size = (SEARCH_MAX / 2) - 4;
@@ -440,18 +1296,27 @@ mod tests {
         let mut input = StringReader::new(&orig_content);
         let patterns = vec!["who", "abba"];
         let replacements = vec!["queen", "queen"];
-        let buf_searcher = BufSearcher::new(&patterns, &replacements, &mut input);
+        let buf_searcher = BufSearcher::new(
+            &patterns,
+            &replacements,
+            SearchConfig::default(),
+            &mut input,
+        );
         let diffs: Vec<_> = buf_searcher.map(|x| x.unwrap()).collect();
         let expected = vec![
             Diff {
                 pos: garbage_size,
                 remove: 3,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: garbage_size,
+                row: 0,
             },
             Diff {
                 pos: (garbage_size * 2) + 4,
                 remove: 4,
-                add: "queen",
+                add: "queen".as_bytes().into(),
+                anchor: garbage_size,
+                row: 1,
             },
         ];
         assert_eq!(diffs, expected);
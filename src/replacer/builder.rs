@@ -0,0 +1,603 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::cancel::CancelToken;
+use crate::replacer::error::{Error, Result};
+use crate::replacer::interactive::Prompt;
+use crate::replacer::options::Options;
+use crate::replacer::pool::ErrorPolicy;
+use crate::replacer::progress::{Observer, Progress};
+use crate::replacer::rectangle::Rectangle;
+use crate::replacer::report::Report;
+use crate::replacer::select::Selection;
+use crate::replacer::spec::SearchSpec;
+use crate::replacer::walker::DirWalker;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a run looks for, and what it puts in its place.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+enum Edit {
+    #[default]
+    Nothing,
+    Search(SearchSpec),
+    Rectangle(Rectangle),
+    /// A pattern whose row, starting at 1, is empty
+    EmptyPattern(usize),
+}
+
+impl Edit {
+    fn search(spec: Result<SearchSpec>) -> Self {
+        match spec {
+            Ok(spec) => Edit::Search(spec),
+            Err(Error::EmptyPattern(row)) => Edit::EmptyPattern(row),
+            Err(_) => Edit::Nothing,
+        }
+    }
+}
+
+/// A search and replace run over files, described step by step.
+///
+/// ```no_run
+/// let report = ved::Ved::new()
+///     .literal("abba", "toto")
+///     .path("src")
+///     .dry_run(true)
+///     .run()?;
+/// for file in &report.files {
+///     println!("{}: {} matches", file.path.display(), file.hits.len());
+/// }
+/// # Ok::<(), ved::Error>(())
+/// ```
+///
+/// A run makes a single edit: the last call to `literal`, `block`, `spec` or `rectangle` wins.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Ved {
+    edit: Edit,
+    paths: Vec<PathBuf>,
+    dry_run: bool,
+    options: Options,
+}
+
+impl Ved {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces `pattern` with `replacement`, both given as text or as bytes.
+    ///
+    /// An empty pattern makes the run fail with `Error::EmptyPattern`.
+    pub fn literal(mut self, pattern: impl Into<Vec<u8>>, replacement: impl Into<Vec<u8>>) -> Self {
+        self.edit = Edit::search(SearchSpec::literal(pattern, replacement));
+        self
+    }
+
+    /// Replaces a block: text aligned on a column across lines, given as its rows along with
+    /// their replacements.
+    ///
+    /// A block without rows leaves nothing to do, and a block with an empty row makes the run fail
+    /// with `Error::EmptyPattern`.
+    pub fn block<I, P, R>(mut self, rows: I) -> Self
+    where
+        I: IntoIterator<Item = (P, R)>,
+        P: Into<Vec<u8>>,
+        R: Into<Vec<u8>>,
+    {
+        self.edit = Edit::search(SearchSpec::new(rows));
+        self
+    }
+
+    /// Searches and replaces the pattern of a spec, which may be shared with other runs.
+    pub fn spec(mut self, spec: SearchSpec) -> Self {
+        self.edit = Edit::Search(spec);
+        self
+    }
+
+    /// Applies a rectangle edit to every line instead of searching.
+    pub fn rectangle(mut self, rectangle: Rectangle) -> Self {
+        self.edit = Edit::Rectangle(rectangle);
+        self
+    }
+
+    /// Adds a file, a directory (processed recursively) or a glob to the files to process.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Adds files, directories or globs to the files to process.
+    pub fn paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only reports what would be replaced, without writing anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Replaces all the settings of the run.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets which occurrences are kept.
+    pub fn selection(mut self, selection: Selection) -> Self {
+        self.options.selection = selection;
+        self
+    }
+
+    /// Sets how the patterns are matched.
+    pub fn search(mut self, search: SearchConfig) -> Self {
+        self.options.search = search;
+        self
+    }
+
+    /// Sets what to do with the other files once one of them failed.
+    pub fn policy(mut self, policy: ErrorPolicy) -> Self {
+        self.options.policy = policy;
+        self
+    }
+
+    /// Tells `progress` about the files as they are processed.
+    pub fn progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.options.progress = Some(Observer::new(progress));
+        self
+    }
+
+    /// Stops the run once `cancel` is cancelled, the files being processed are left as they were.
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// Skips the files taking more than `timeout` to search, leaving them as they were.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Processes the files of all the paths.
+    ///
+    /// Under `ErrorPolicy::FailFast`, the first error is returned instead of a report. A block
+    /// giving a replacement for a row that matches any line fails with `Error::ReplacedAnyRow`
+    /// before any file is read.
+    pub fn run(&self) -> Result<Report> {
+        let options = &self.options;
+        let budget = options.selection.budget();
+        let budget = budget.as_ref();
+        let pool = super::pool(options);
+        match &self.edit {
+            Edit::Nothing => Err(Error::NothingToDo),
+            Edit::EmptyPattern(row) => Err(Error::EmptyPattern(*row)),
+            Edit::Search(spec) if self.dry_run => super::run_files(pool, self.files()?, |path| {
+                super::check_file(spec, path, options, budget)
+            }),
+            Edit::Search(spec) => {
+                check_replacements(spec, options)?;
+                super::run_files(pool, self.files()?, |path| {
+                    super::replace_file(spec, path, options, budget)
+                })
+            }
+            Edit::Rectangle(rectangle) if self.dry_run => {
+                super::run_files(pool, self.files()?, |path| {
+                    super::inspect_file(path, options.progress.as_deref(), |input| {
+                        let counts = super::edit_stream_budgeted(
+                            rectangle,
+                            input,
+                            io::sink(),
+                            options,
+                            budget,
+                        )?;
+                        Ok((Vec::new(), counts))
+                    })
+                })
+            }
+            Edit::Rectangle(rectangle) => super::run_files(pool, self.files()?, |path| {
+                super::edit_file(rectangle, path, options, budget)
+            }),
+        }
+    }
+
+    /// Processes the files of all the paths one after the other, each match being only replaced
+    /// if the user accepts it through `prompt`.
+    ///
+    /// A dry run asks nothing and is the same as `run`. A rectangle edit has no matches to
+    /// confirm and fails with `Error::NotConfirmable`.
+    pub fn run_interactive<In, Out>(&self, prompt: &mut Prompt<In, Out>) -> Result<Report>
+    where
+        In: BufRead + Send,
+        Out: Write + Send,
+    {
+        match &self.edit {
+            _ if self.dry_run => self.run(),
+            Edit::Nothing => Err(Error::NothingToDo),
+            Edit::EmptyPattern(row) => Err(Error::EmptyPattern(*row)),
+            Edit::Search(spec) => {
+                check_replacements(spec, &self.options)?;
+                super::confirm_files(spec, self.files()?, &self.options, prompt)
+            }
+            Edit::Rectangle(_) => Err(Error::NotConfirmable),
+        }
+    }
+
+    /// Returns the files of all the paths, lazily.
+    fn files(&self) -> Result<impl Iterator<Item = Result<PathBuf>> + Send + '_> {
+        let mut files: Vec<Box<dyn Iterator<Item = Result<PathBuf>> + Send>> = Vec::new();
+        for path in &self.paths {
+            if path.is_dir() {
+                files.push(Box::new(DirWalker::new(path.clone())));
+            } else {
+                let glob = path
+                    .to_str()
+                    .ok_or_else(|| Error::PathError(format!("{path:?}")))?;
+                files.push(Box::new(super::glob_files(glob)?));
+            }
+        }
+        Ok(files.into_iter().flatten())
+    }
+}
+
+/// Fails when a block gives a replacement for a row that matches any line, before any file is
+/// read.
+fn check_replacements(spec: &SearchSpec, options: &Options) -> Result<()> {
+    match options.search.replaced_any_row(spec.replacements()) {
+        None => Ok(()),
+        Some(row) => Err(Error::ReplacedAnyRow(row)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replacer::diff::Diff;
+    use crate::replacer::report::{FileReport, SkipReason};
+    use crate::replacer::rows::NumberedRule;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    fn temp_dir() -> tempfile::TempDir {
+        let result = tempfile::tempdir();
+        assert!(result.is_ok());
+        result.unwrap()
+    }
+
+    fn write_file<P: AsRef<Path>>(path: P, content: &str) {
+        fs::write(path, content).unwrap();
+    }
+
+    fn file_content<P: AsRef<Path>>(path: P) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_literal() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba");
+        let report = Ved::new()
+            .literal("abba", "toto")
+            .path(&path)
+            .run()
+            .unwrap();
+        assert_eq!(file_content(&path), "toto toto");
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].path, path);
+        assert_eq!(report.nb_kept(), 2);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "who\nabba\n who\n abba");
+        let report = Ved::new()
+            .block([("who", "queen"), ("abba", "queen")])
+            .path(dir.path())
+            .dry_run(true)
+            .run()
+            .unwrap();
+        assert_eq!(file_content(&path), "who\nabba\n who\n abba");
+        let hits: Vec<_> = report.files[0].hits.iter().map(|h| h.line).collect();
+        assert_eq!(hits, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        fs::write(&path, b"\xca\xfe\xba\xbe\x00\x01").unwrap();
+        let report = Ved::new()
+            .literal(&b"\xba\xbe\x00"[..], vec![0xffu8])
+            .path(&path)
+            .run()
+            .unwrap();
+        assert_eq!(report.nb_kept(), 1);
+        assert_eq!(fs::read(&path).unwrap(), b"\xca\xfe\xff\x01");
+    }
+
+    #[test]
+    fn test_shared_spec() {
+        let dir = temp_dir();
+        let paths = [dir.path().join("file1"), dir.path().join("file2")];
+        let spec = SearchSpec::new([("who", "queen"), ("abba", "queen")]).unwrap();
+        let runs: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                write_file(path, "who\nabba");
+                let ved = Ved::new().spec(spec.clone()).path(path);
+                std::thread::spawn(move || ved.run())
+            })
+            .collect();
+        for run in runs {
+            assert_eq!(run.join().unwrap().unwrap().nb_kept(), 1);
+        }
+        for path in &paths {
+            assert_eq!(file_content(path), "queen\nqueen");
+        }
+        assert!(matches!(
+            Ved::new().block(Vec::<(&str, &str)>::new()).run(),
+            Err(Error::NothingToDo)
+        ));
+    }
+
+    #[test]
+    fn test_paths_share_the_budget() {
+        let dir = temp_dir();
+        let paths = [dir.path().join("file1"), dir.path().join("file2")];
+        for path in &paths {
+            write_file(path, "abba abba");
+        }
+        let selection = Selection {
+            max_total: Some(3),
+            ..Selection::default()
+        };
+        let report = Ved::new()
+            .literal("abba", "toto")
+            .paths(&paths)
+            .selection(selection)
+            .run()
+            .unwrap();
+        assert_eq!(file_content(&paths[0]), "toto toto");
+        assert_eq!(file_content(&paths[1]), "toto abba");
+        assert_eq!(report.nb_skipped(), 1);
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abcd\nabcd");
+        let rectangle = Rectangle {
+            start: 1,
+            end: 3,
+            text: Vec::new(),
+        };
+        let ved = Ved::new().rectangle(rectangle).path(&path);
+        let report = ved.clone().dry_run(true).run().unwrap();
+        assert_eq!(report.nb_kept(), 2);
+        assert_eq!(file_content(&path), "abcd\nabcd");
+        ved.run().unwrap();
+        assert_eq!(file_content(&path), "ad\nad");
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = temp_dir();
+        let missing = dir.path().join("missing");
+        assert!(matches!(Ved::new().run(), Err(Error::NothingToDo)));
+        let ved = Ved::new().literal("abba", "toto").path(&missing);
+        let report = ved.run().unwrap();
+        assert!(report.files.is_empty());
+        // a missing file is an empty glob
+        assert!(report.errors.is_empty());
+        let path = dir.path().join("file");
+        write_file(&path, "a\nx\nc");
+        let search = SearchConfig {
+            rows: NumberedRule::collect(&["2:any".parse().unwrap()]),
+            ..SearchConfig::default()
+        };
+        let ved = Ved::new()
+            .block([("a", "A"), ("?", "?")])
+            .search(search)
+            .path(&path);
+        assert!(matches!(ved.run(), Err(Error::ReplacedAnyRow(2))));
+        assert_eq!(file_content(&path), "a\nx\nc");
+        let ved = Ved::new().literal("", "x").path(&path);
+        assert!(matches!(ved.run(), Err(Error::EmptyPattern(1))));
+        let ved = ved.block([("a", "A"), ("", "")]).dry_run(true);
+        assert!(matches!(ved.run(), Err(Error::EmptyPattern(2))));
+        assert_eq!(file_content(&path), "a\nx\nc");
+    }
+
+    #[test]
+    fn test_run_interactive() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba abba");
+        let mut prompt = Prompt::new(io::Cursor::new("y\nn\ny\n"), Vec::new());
+        let ved = Ved::new().literal("abba", "toto").path(&path);
+        let report = ved.run_interactive(&mut prompt).unwrap();
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(file_content(&path), "toto abba toto");
+
+        let rectangle = Rectangle {
+            start: 1,
+            end: 3,
+            text: Vec::new(),
+        };
+        let ved = Ved::new().rectangle(rectangle).path(&path);
+        let result = ved.run_interactive(&mut prompt);
+        assert!(matches!(result, Err(Error::NotConfirmable)));
+    }
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl Progress for Events {
+        fn file_started(&self, _path: &Path, size: u64) {
+            self.0.lock().unwrap().push(format!("started {size}"));
+        }
+
+        fn bytes_processed(&self, nb_bytes: u64) {
+            self.0.lock().unwrap().push(format!("bytes {nb_bytes}"));
+        }
+
+        fn match_found(&self, diff: &Diff) {
+            self.0.lock().unwrap().push(format!("match {}", diff.pos));
+        }
+
+        fn file_finished(&self, report: &FileReport) {
+            let found = report.counts.found;
+            self.0.lock().unwrap().push(format!("finished {found}"));
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba and abba");
+        let events = Arc::new(Events::default());
+        let ved = Ved::new().literal("abba", "toto").path(&path);
+        ved.clone().progress(events.clone()).run().unwrap();
+        let expected = [
+            "started 13",
+            "match 0",
+            "bytes 4",
+            "bytes 5",
+            "match 9",
+            "bytes 4",
+            "finished 2",
+        ];
+        assert_eq!(*events.0.lock().unwrap(), expected);
+        events.0.lock().unwrap().clear();
+        ved.dry_run(true).progress(events.clone()).run().unwrap();
+        let expected = ["started 13", "bytes 13", "finished 0"];
+        assert_eq!(*events.0.lock().unwrap(), expected);
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_timeout() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba");
+        let ved = Ved::new().literal("abba", "toto").path(&path);
+        let report = ved.clone().timeout(Duration::ZERO).run().unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+        assert!(report.errors.is_empty());
+        assert_eq!(file_content(&path), "abba abba");
+        assert_eq!(file_names(dir.path()), ["file"]);
+        let report = ved.dry_run(true).timeout(Duration::ZERO).run().unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+
+        let rectangle = Rectangle {
+            start: 1,
+            end: 3,
+            text: Vec::new(),
+        };
+        let ved = Ved::new().rectangle(rectangle).path(&path);
+        let report = ved.clone().timeout(Duration::ZERO).run().unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+        assert_eq!(file_content(&path), "abba abba");
+        assert_eq!(file_names(dir.path()), ["file"]);
+        let report = ved.dry_run(true).timeout(Duration::ZERO).run().unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+    }
+
+    /// Cancels the run as soon as a file is started.
+    struct CancelOnStart(CancelToken);
+
+    impl Progress for CancelOnStart {
+        fn file_started(&self, _path: &Path, _size: u64) {
+            self.0.cancel();
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let dir = temp_dir();
+        for name in ["a", "b", "c"] {
+            write_file(dir.path().join(name), "abba");
+        }
+        let cancel = CancelToken::new();
+        let report = Ved::new()
+            .literal("abba", "toto")
+            .path(dir.path())
+            .cancel(cancel.clone())
+            .progress(Arc::new(CancelOnStart(cancel.clone())))
+            .run()
+            .unwrap();
+        assert!(cancel.is_cancelled());
+        assert!(!report.files.is_empty());
+        for file in &report.files {
+            assert_eq!(file.skipped, Some(SkipReason::Cancelled));
+        }
+        assert_eq!(file_names(dir.path()), ["a", "b", "c"]);
+        for name in ["a", "b", "c"] {
+            assert_eq!(file_content(dir.path().join(name)), "abba");
+        }
+        let report = Ved::new()
+            .literal("abba", "toto")
+            .path(dir.path())
+            .cancel(cancel)
+            .run()
+            .unwrap();
+        assert!(report.files.is_empty());
+    }
+
+    /// Cancels the run as soon as an occurrence is replaced.
+    struct CancelOnMatch(CancelToken);
+
+    impl Progress for CancelOnMatch {
+        fn match_found(&self, _diff: &Diff) {
+            self.0.cancel();
+        }
+    }
+
+    #[test]
+    fn test_cancel_while_replacing() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba\nabba\nabba");
+        let rectangle = Rectangle {
+            start: 0,
+            end: 1,
+            text: b"A".to_vec(),
+        };
+        let runs = [
+            Ved::new().literal("abba", "toto"),
+            Ved::new().rectangle(rectangle),
+        ];
+        for ved in runs {
+            let cancel = CancelToken::new();
+            let report = ved
+                .path(&path)
+                .cancel(cancel.clone())
+                .progress(Arc::new(CancelOnMatch(cancel.clone())))
+                .run()
+                .unwrap();
+            assert!(cancel.is_cancelled());
+            assert_eq!(report.files[0].skipped, Some(SkipReason::Cancelled));
+            assert_eq!(file_content(&path), "abba\nabba\nabba");
+            assert_eq!(file_names(dir.path()), ["file"]);
+        }
+    }
+}
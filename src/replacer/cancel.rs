@@ -0,0 +1,182 @@
+use crate::replacer::diff::Diff;
+use crate::replacer::error::{Error, Result};
+use crate::replacer::options::Options;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stops a run from another thread.
+///
+/// The files that were not started are not processed. The ones being processed are left as they
+/// were and reported as skipped, their temporary files being removed. The clones of a token share
+/// it: cancelling one cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// Tells a search over a stream when to give up: once the run is cancelled, or once the stream
+/// took more than the timeout.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Interrupt {
+    cancel: Option<CancelToken>,
+    timeout: Option<(Duration, Instant)>,
+}
+
+impl Interrupt {
+    /// Returns the interrupt of a stream starting now.
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            cancel: options.cancel.clone(),
+            timeout: options.timeout.map(|t| (t, Instant::now() + t)),
+        }
+    }
+
+    /// Fails with `Error::Cancelled` or `Error::TimedOut` when the search must stop.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(Error::Cancelled);
+        }
+        match self.timeout {
+            Some((timeout, deadline)) if Instant::now() >= deadline => {
+                Err(Error::TimedOut(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A reader giving up on its stream with the error of an Interrupt, checked before and after each
+/// read: the bytes read at once may take long to search.
+pub(crate) struct Interruptible<R> {
+    reader: R,
+    interrupt: Interrupt,
+}
+
+impl Interrupt {
+    /// Returns `reader`, giving up on its stream when the search must stop.
+    pub(crate) fn reader<R: Read>(self, reader: R) -> Interruptible<R> {
+        Interruptible {
+            reader,
+            interrupt: self,
+        }
+    }
+
+    /// Returns `diffs`, giving up before each diff when the search must stop.
+    pub(crate) fn diffs<'s, I>(self, diffs: I) -> Interrupted<I>
+    where
+        I: Iterator<Item = Result<Diff<'s>>>,
+    {
+        Interrupted {
+            diffs,
+            interrupt: self,
+        }
+    }
+}
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupt.check().map_err(io::Error::other)?;
+        let len = self.reader.read(buf)?;
+        self.interrupt.check().map_err(io::Error::other)?;
+        Ok(len)
+    }
+}
+
+impl<R: Seek> Seek for Interruptible<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+/// Diffs giving up with the error of an Interrupt, checked before each diff: a search may go
+/// through many bytes it already read before finding the next one.
+pub(crate) struct Interrupted<I> {
+    diffs: I,
+    interrupt: Interrupt,
+}
+
+impl<'s, I> Iterator for Interrupted<I>
+where
+    I: Iterator<Item = Result<Diff<'s>>>,
+{
+    type Item = Result<Diff<'s>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'s>>> {
+        match self.interrupt.check() {
+            Ok(()) => self.diffs.next(),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(Interrupt::default().check().is_ok());
+        let cancel = CancelToken::new();
+        let options = Options {
+            cancel: Some(cancel.clone()),
+            ..Options::default()
+        };
+        let interrupt = Interrupt::new(&options);
+        assert!(interrupt.check().is_ok());
+        cancel.clone().cancel();
+        assert!(matches!(interrupt.check(), Err(Error::Cancelled)));
+        let options = Options {
+            timeout: Some(Duration::ZERO),
+            ..Options::default()
+        };
+        let interrupt = Interrupt::new(&options);
+        assert!(matches!(interrupt.check(), Err(Error::TimedOut(_))));
+        let mut reader = interrupt.reader(io::Cursor::new("abba"));
+        let e = Error::from(reader.read(&mut [0; 4]).unwrap_err());
+        assert!(matches!(e, Error::TimedOut(_)));
+    }
+
+    #[test]
+    fn test_diffs() {
+        let cancel = CancelToken::new();
+        let options = Options {
+            cancel: Some(cancel.clone()),
+            ..Options::default()
+        };
+        let diffs = (0..3).map(|pos| {
+            Ok(Diff {
+                pos,
+                remove: 1,
+                add: b"".into(),
+                anchor: pos,
+                row: 0,
+            })
+        });
+        let mut diffs = Interrupt::new(&options).diffs(diffs);
+        assert!(matches!(diffs.next(), Some(Ok(Diff { pos: 0, .. }))));
+        cancel.cancel();
+        assert!(matches!(diffs.next(), Some(Err(Error::Cancelled))));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use crate::replacer::lines::LineCounter;
+use crate::replacer::separator::Separator;
+use std::io::Read;
+
+/// A place in a file where one row of a pattern was found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hit {
+    /// The offset of the match with the start of the file
+    pub pos: usize,
+    /// The number of bytes matched
+    pub len: usize,
+    /// The line of the match, starting at 1
+    pub line: usize,
+    /// The column of the match in bytes, starting at 1
+    pub column: usize,
+}
+
+/// Turns the Diffs found by a BufSearcher into Hits.
+///
+/// The Checker reads the original stream alongside the searcher, like a Replacer does, but only
+/// to count lines: nothing is ever written.
+pub struct Checker<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+    lines: LineCounter<R>,
+}
+
+impl<'search, 'iterator, R> Checker<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    pub fn new(
+        diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+        original: R,
+        separator: Separator,
+    ) -> Self {
+        Self {
+            diffs,
+            lines: LineCounter::new(original, separator),
+        }
+    }
+
+    fn next_hit(&mut self) -> Result<Option<Hit>> {
+        let diff = match self.diffs.next() {
+            None => return Ok(None),
+            Some(diff) => diff?,
+        };
+        let (line, column) = self.lines.locate(diff.pos)?;
+        Ok(Some(Hit {
+            pos: diff.pos,
+            len: diff.remove,
+            line,
+            column,
+        }))
+    }
+}
+
+impl<'search, 'iterator, R> Iterator for Checker<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    type Item = Result<Hit>;
+
+    fn next(&mut self) -> Option<Result<Hit>> {
+        self.next_hit().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use stringreader::StringReader;
+
+    fn diff(pos: usize, remove: usize) -> Result<Diff<'static>> {
+        Ok(Diff {
+            pos,
+            remove,
+            add: Cow::Borrowed(&[]),
+            anchor: pos,
+            row: 0,
+        })
+    }
+
+    #[test]
+    fn test_no_diffs() {
+        let mut original = StringReader::new("abba");
+        let checker = Checker::new(
+            Box::new(std::iter::empty()),
+            &mut original,
+            Separator::default(),
+        );
+        let hits: Vec<_> = checker.map(|h| h.unwrap()).collect();
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_lines_and_columns() {
+        let mut original = StringReader::new("abba\n_abba\n\n__abba");
+        let diffs = vec![diff(0, 4), diff(6, 4), diff(14, 4)];
+        let checker = Checker::new(
+            Box::new(diffs.into_iter()),
+            &mut original,
+            Separator::default(),
+        );
+        let hits: Vec<_> = checker.map(|h| h.unwrap()).collect();
+        let expected = vec![
+            Hit {
+                pos: 0,
+                len: 4,
+                line: 1,
+                column: 1,
+            },
+            Hit {
+                pos: 6,
+                len: 4,
+                line: 2,
+                column: 2,
+            },
+            Hit {
+                pos: 14,
+                len: 4,
+                line: 4,
+                column: 3,
+            },
+        ];
+        assert_eq!(hits, expected);
+    }
+
+    #[test]
+    fn test_two_hits_on_one_line() {
+        let mut original = StringReader::new("\nabba abba");
+        let diffs = vec![diff(1, 4), diff(6, 4)];
+        let checker = Checker::new(
+            Box::new(diffs.into_iter()),
+            &mut original,
+            Separator::default(),
+        );
+        let hits: Vec<_> = checker
+            .map(|h| h.unwrap())
+            .map(|h| (h.line, h.column))
+            .collect();
+        assert_eq!(hits, vec![(2, 1), (2, 6)]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::str::FromStr;
+use unicode_width::UnicodeWidthChar;
+
+/// How the columns of block patterns are counted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ColumnMode {
+    /// One column per byte
+    #[default]
+    Bytes,
+    /// One column per Unicode scalar value
+    Chars,
+    /// The width of the characters on a terminal, from the Unicode width tables: East Asian wide
+    /// characters and emoji take two columns and combining characters take none, so that the
+    /// grapheme clusters take the width of their base
+    Width,
+}
+
+impl ColumnMode {
+    /// Returns the number of columns taken by the byte at the start of `bytes`.
+    ///
+    /// In the modes counting characters, the whole character is counted on its first byte, and
+    /// its other bytes take no column.
+    pub fn width(self, bytes: &[u8]) -> usize {
+        match self {
+            ColumnMode::Bytes => 1,
+            _ if is_continuation(bytes[0]) => 0,
+            ColumnMode::Chars => 1,
+            ColumnMode::Width => match decode(bytes) {
+                None => 1,
+                Some(c) => char_width(c),
+            },
+        }
+    }
+
+    /// Returns whether `byte` continues a character instead of starting one, in the modes
+    /// counting characters.
+    pub fn continues_char(self, byte: u8) -> bool {
+        self != ColumnMode::Bytes && is_continuation(byte)
+    }
+}
+
+/// Which column the rows of a block share.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Align {
+    /// The rows start at the same column
+    #[default]
+    Start,
+    /// The rows end at the same column, as in right-aligned text
+    End,
+}
+
+impl FromStr for Align {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "start" => Ok(Align::Start),
+            "end" => Ok(Align::End),
+            _ => Err(format!("unknown alignment {s:?}, expected start or end")),
+        }
+    }
+}
+
+impl FromStr for ColumnMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "bytes" => Ok(ColumnMode::Bytes),
+            "chars" => Ok(ColumnMode::Chars),
+            "width" => Ok(ColumnMode::Width),
+            _ => Err(format!(
+                "unknown column mode {s:?}, expected bytes, chars or width"
+            )),
+        }
+    }
+}
+
+/// Returns the rows of a block matching `text` written vertically, one character per row.
+///
+/// The characters that take no column, like the combining ones, stay with the character they
+/// follow.
+pub fn vertical(text: &str) -> Vec<&str> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    for (index, c) in text.char_indices().skip(1) {
+        if char_width(c) > 0 {
+            rows.push(&text[start..index]);
+            start = index;
+        }
+    }
+    if start < text.len() {
+        rows.push(&text[start..]);
+    }
+    rows
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// Decodes the character at the start of `bytes`, if it is valid UTF-8.
+fn decode(bytes: &[u8]) -> Option<char> {
+    let len = match bytes[0] {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    };
+    let s = std::str::from_utf8(bytes.get(..len)?).ok()?;
+    s.chars().next()
+}
+
+/// Returns the number of columns taken by `c` on a terminal, from the Unicode width tables.
+///
+/// The control characters take one column, the tabs being expanded separately.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(mode: ColumnMode, s: &str) -> usize {
+        let bytes = s.as_bytes();
+        (0..bytes.len()).map(|i| mode.width(&bytes[i..])).sum()
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert_eq!(columns(ColumnMode::Bytes, "abba"), 4);
+        assert_eq!(columns(ColumnMode::Chars, "abba"), 4);
+        assert_eq!(columns(ColumnMode::Width, "abba"), 4);
+    }
+
+    #[test]
+    fn test_accents() {
+        assert_eq!(columns(ColumnMode::Bytes, "été"), 5);
+        assert_eq!(columns(ColumnMode::Chars, "été"), 3);
+        assert_eq!(columns(ColumnMode::Width, "été"), 3);
+    }
+
+    #[test]
+    fn test_combining() {
+        let s = "e\u{301}t\u{65}\u{301}";
+        assert_eq!(columns(ColumnMode::Chars, s), 5);
+        assert_eq!(columns(ColumnMode::Width, s), 3);
+    }
+
+    #[test]
+    fn test_wide() {
+        assert_eq!(columns(ColumnMode::Chars, "日本a"), 3);
+        assert_eq!(columns(ColumnMode::Width, "日本a"), 5);
+    }
+
+    #[test]
+    fn test_width_tables() {
+        // A Devanagari combining mark and an emoji of the transport block
+        assert_eq!(columns(ColumnMode::Width, "क\u{901}"), 1);
+        assert_eq!(columns(ColumnMode::Width, "\u{1F680}a"), 3);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes = [0xE6, 0x97, b'a'];
+        let width: usize = (0..bytes.len())
+            .map(|i| ColumnMode::Width.width(&bytes[i..]))
+            .sum();
+        assert_eq!(width, 2);
+    }
+
+    #[test]
+    fn test_vertical() {
+        assert_eq!(vertical("abba"), vec!["a", "b", "b", "a"]);
+        assert_eq!(vertical("日e\u{301}t"), vec!["日", "e\u{301}", "t"]);
+        assert!(vertical("").is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("width".parse(), Ok(ColumnMode::Width));
+        assert!("graphemes".parse::<ColumnMode>().is_err());
+        assert_eq!("end".parse(), Ok(Align::End));
+        assert!("right".parse::<Align>().is_err());
+    }
+
+    #[test]
+    fn test_continues_char() {
+        let bytes = "é".as_bytes();
+        assert!(!ColumnMode::Chars.continues_char(bytes[0]));
+        assert!(ColumnMode::Chars.continues_char(bytes[1]));
+        assert!(!ColumnMode::Bytes.continues_char(bytes[1]));
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// A writer comparing what it writes with the original content, to tell whether a rewrite
+/// changed anything at all: replacing a text with itself leaves a file as it was.
+pub(crate) struct Compare<W, R> {
+    output: W,
+    original: BufReader<R>,
+    written: u64,
+    differs: bool,
+}
+
+impl<W: Write, R: Read> Compare<W, R> {
+    pub(crate) fn new(output: W, original: R) -> Self {
+        Self {
+            output,
+            original: BufReader::new(original),
+            written: 0,
+            differs: false,
+        }
+    }
+
+    /// Returns the number of bytes written, and whether they differ from the original, which is
+    /// the case when it goes on after them.
+    pub(crate) fn finish(mut self) -> io::Result<(u64, bool)> {
+        self.output.flush()?;
+        if !self.differs {
+            self.differs = !self.original.fill_buf()?.is_empty();
+        }
+        Ok((self.written, self.differs))
+    }
+
+    /// Whether `bytes` are the next bytes of the original, which are consumed.
+    fn matches(&mut self, mut bytes: &[u8]) -> io::Result<bool> {
+        while !bytes.is_empty() {
+            let available = self.original.fill_buf()?;
+            let len = available.len().min(bytes.len());
+            if len == 0 || available[..len] != bytes[..len] {
+                return Ok(false);
+            }
+            self.original.consume(len);
+            bytes = &bytes[len..];
+        }
+        Ok(true)
+    }
+}
+
+impl<W: Write, R: Read> Write for Compare<W, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.output.write(buf)?;
+        self.written += len as u64;
+        if !self.differs {
+            self.differs = !self.matches(&buf[..len])?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(original: &str, chunks: &[&str]) -> (Vec<u8>, u64, bool) {
+        let mut output = Vec::new();
+        let mut compare = Compare::new(&mut output, original.as_bytes());
+        for chunk in chunks {
+            compare.write_all(chunk.as_bytes()).unwrap();
+        }
+        let (written, differs) = compare.finish().unwrap();
+        (output, written, differs)
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare("abba", &["ab", "ba"]), (b"abba".to_vec(), 4, false));
+        assert_eq!(compare("abba", &["ab", "bo"]), (b"abbo".to_vec(), 4, true));
+        assert_eq!(compare("abba", &["ab"]), (b"ab".to_vec(), 2, true));
+        assert_eq!(compare("ab", &["ab", "ba"]), (b"abba".to_vec(), 4, true));
+        assert_eq!(compare("", &[]), (Vec::new(), 0, false));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use crate::replacer::lines::LineCounter;
+use std::borrow::Cow;
+use std::io::Read;
+
+/// What a replacement callback knows about an occurrence.
+///
+/// There are no captures: the patterns of a spec are literal, and a Matcher gives its occurrences
+/// as the bytes they replace, so `bytes` holds the whole of what was matched.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MatchInfo<'a> {
+    /// The bytes matched, the ones of the row for a block, as read from the stream
+    pub bytes: &'a [u8],
+    /// The offset of the match with the start of the stream
+    pub pos: usize,
+    /// The line of the match, starting at 1
+    pub line: usize,
+    /// The column of the match, starting at 1, counted like the columns of a block are with the
+    /// search config: in bytes, characters or width, with the tabs expanded to the tab stop
+    pub column: usize,
+    /// The row of the block pattern that matched, starting at 0
+    pub row: usize,
+    /// The offset of the first row of the occurrence, equal to `pos` for a single row
+    pub anchor: usize,
+}
+
+/// Replaces the text added by Diffs with the bytes computed by a callback.
+///
+/// The original stream is read alongside the Diffs, like a Checker does, to locate them and to
+/// read the bytes they match.
+pub struct Computed<'search, 'iterator, F, R>
+where
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]>,
+    R: Read,
+    'search: 'iterator,
+{
+    diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+    replace: &'iterator F,
+    lines: LineCounter<R>,
+}
+
+impl<'search, 'iterator, F, R> Computed<'search, 'iterator, F, R>
+where
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]>,
+    R: Read,
+    'search: 'iterator,
+{
+    /// Creates the replacements of `diffs`, found in `original` with the search `config`.
+    pub fn new(
+        diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+        replace: &'iterator F,
+        original: R,
+        config: SearchConfig,
+    ) -> Self {
+        Self {
+            diffs,
+            replace,
+            lines: LineCounter::with_columns(original, config),
+        }
+    }
+
+    fn compute(&mut self, diff: Diff<'search>) -> Result<Diff<'search>> {
+        let (line, column) = self.lines.locate(diff.pos)?;
+        let bytes = self.lines.take(diff.remove)?;
+        let info = MatchInfo {
+            bytes: &bytes,
+            pos: diff.pos,
+            line,
+            column,
+            row: diff.row,
+            anchor: diff.anchor,
+        };
+        let add = (self.replace)(&info).into_owned();
+        Ok(Diff {
+            add: Cow::Owned(add),
+            ..diff
+        })
+    }
+}
+
+impl<'search, 'iterator, F, R> Iterator for Computed<'search, 'iterator, F, R>
+where
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]>,
+    R: Read,
+    'search: 'iterator,
+{
+    type Item = Result<Diff<'search>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'search>>> {
+        let diff = self.diffs.next()?;
+        Some(diff.and_then(|d| self.compute(d)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replacer::columns::ColumnMode;
+    use std::num::NonZeroUsize;
+    use stringreader::StringReader;
+
+    fn diff(pos: usize, row: usize) -> Result<Diff<'static>> {
+        Ok(Diff {
+            pos,
+            remove: 4,
+            add: "toto".as_bytes().into(),
+            anchor: 0,
+            row,
+        })
+    }
+
+    #[test]
+    fn test_compute() {
+        let diffs = vec![diff(0, 0), diff(5, 1)];
+        let mut original = StringReader::new("abba\nABBA");
+        let computed = Computed::new(
+            Box::new(diffs.into_iter()),
+            &|info| {
+                let text = format!("{}:{}:{}", info.line, info.column, info.row);
+                Cow::Owned(text.into_bytes())
+            },
+            &mut original,
+            SearchConfig::default(),
+        );
+        let added: Vec<_> = computed.map(|d| d.unwrap().add.into_owned()).collect();
+        assert_eq!(added, vec![b"1:1:0".to_vec(), b"2:1:1".to_vec()]);
+    }
+
+    #[test]
+    fn test_columns_and_bytes() {
+        let diffs = vec![diff(3, 0), diff(14, 0)];
+        let mut original = StringReader::new("\tx\tabba\tété\tAbBa");
+        let config = SearchConfig {
+            columns: ColumnMode::Chars,
+            tabstop: NonZeroUsize::new(4),
+            ..SearchConfig::default()
+        };
+        let computed = Computed::new(
+            Box::new(diffs.into_iter()),
+            &|info| {
+                let bytes = String::from_utf8_lossy(info.bytes);
+                Cow::Owned(format!("{}:{bytes}", info.column).into_bytes())
+            },
+            &mut original,
+            config,
+        );
+        let added: Vec<_> = computed.map(|d| d.unwrap().add.into_owned()).collect();
+        assert_eq!(added, vec![b"9:abba".to_vec(), b"21:AbBa".to_vec()]);
+    }
+
+    #[test]
+    fn test_borrowed_bytes() {
+        let mut original = StringReader::new("abba");
+        let computed = Computed::new(
+            Box::new(vec![diff(0, 0)].into_iter()),
+            &|info| Cow::Borrowed(&info.bytes[1..3]),
+            &mut original,
+            SearchConfig::default(),
+        );
+        let added: Vec<_> = computed.map(|d| d.unwrap().add.into_owned()).collect();
+        assert_eq!(added, vec![b"bb".to_vec()]);
+    }
+}
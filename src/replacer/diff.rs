@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 #[derive(Ord, PartialOrd, Debug, Eq, PartialEq)]
 pub struct Diff<'str> {
     /// The offset of the diff with the start of the file
@@ -8,9 +10,25 @@ pub struct Diff<'str> {
     pub pos: usize,
     /// The number of characters to remove
     pub remove: usize,
-    /// The string to add
-    pub add: &'str str,
+    /// The bytes to add
+    pub add: Cow<'str, [u8]>,
+    /// The offset of the first row of the match this diff belongs to
+    ///
+    /// For single-line patterns, this is always equal to pos.
+    pub anchor: usize,
+    /// The index of the pattern row that produced this diff
+    pub row: usize,
 }
+
+impl<'str> Diff<'str> {
+    /// Returns whether applying the diff changes nothing.
+    ///
+    /// Such diffs stand for the rows of a block where nothing is replaced.
+    pub fn is_noop(&self) -> bool {
+        self.remove == 0 && self.add.is_empty()
+    }
+}
+
 /*
 impl<'str> Ord for Diff<'str> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
@@ -1,26 +1,149 @@
 use glob;
 use std::any::Any;
+use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("end of iteration")]
-    EndOfIteration,
     #[error("cannot handle path: {0}")]
     PathError(String),
     #[error("IO error: {0}")]
-    IoError(#[from] io::Error),
+    IoError(io::Error),
     #[error("Glob error: {0}")]
     GlobError(#[from] glob::GlobError),
     #[error("Pattern error: {0}")]
     PatternError(#[from] glob::PatternError),
     #[error("internal error: {0}")]
     Internal(#[from] Box<Error>),
+    #[error(
+        "a match takes at least {pattern} bytes, more than the search window of {window} bytes"
+    )]
+    WindowTooSmall { pattern: usize, window: usize },
+    #[error("nothing to search or edit")]
+    NothingToDo,
+    /// An empty row of a pattern, starting at 1, which would match at every byte
+    #[error("row {0} of the pattern is empty")]
+    EmptyPattern(usize),
+    /// A replacement given for a row of a block that matches any line, starting at 1
+    #[error("row {0} of the block matches any line, it cannot be replaced")]
+    ReplacedAnyRow(usize),
+    /// A match starting at `pos`, before the end of the previous match at `end`, which a matcher
+    /// must not find
+    #[error("the match at byte {pos} starts before the end of the previous match at byte {end}")]
+    UnorderedMatch { pos: usize, end: usize },
+    #[error("only the matches of a search can be confirmed one by one")]
+    NotConfirmable,
     #[error("thread panic: {0}")]
     ThreadPanic(String),
+    #[error("cancelled")]
+    Cancelled,
+    #[error("took more than {0:?}")]
+    TimedOut(Duration),
+    /// An I/O error while replacing in a stream, `offset` being the position in the original up
+    /// to which the output was written
+    #[error("at byte {offset}: {source}")]
+    Stream { offset: usize, source: Box<Error> },
+    /// An error while processing a file, along with the step that failed
+    #[error("cannot {operation} {}{}: {source}", path.display(), at_offset(*offset))]
+    File {
+        path: PathBuf,
+        operation: Operation,
+        offset: Option<usize>,
+        source: Box<Error>,
+    },
+}
+
+/// The step of the processing of a file that failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Opening the file, or reading its size
+    Open,
+    /// Listing the entries of a directory
+    Walk,
+    /// Creating the temporary file receiving the new content
+    CreateTemp,
+    /// Searching the file without modifying it
+    Read,
+    /// Searching the file and writing its new content to the temporary file
+    Rewrite,
+    /// Renaming the temporary file over the file
+    Rename,
+    /// Anything else, like a panic of the code processing the file
+    Process,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Open => "open",
+            Operation::Walk => "list the entries of",
+            Operation::CreateTemp => "create a temporary file for",
+            Operation::Read => "read",
+            Operation::Rewrite => "rewrite",
+            Operation::Rename => "rename a temporary file to",
+            Operation::Process => "process",
+        })
+    }
+}
+
+fn at_offset(offset: Option<usize>) -> String {
+    offset.map_or_else(String::new, |offset| format!(" at byte {offset}"))
+}
+
+impl Error {
+    /// Returns the error as it happened while processing `path`.
+    ///
+    /// The offset of a stream error is kept in the file error.
+    pub(crate) fn in_file(self, path: &Path, operation: Operation) -> Self {
+        let (offset, source) = match self {
+            Error::Stream { offset, source } => (Some(offset), source),
+            e => (None, Box::new(e)),
+        };
+        Error::File {
+            path: path.to_owned(),
+            operation,
+            offset,
+            source,
+        }
+    }
+
+    /// Returns the path of the file the error is about, if it is known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::File { path, .. } => Some(path),
+            Error::GlobError(e) => Some(e.path()),
+            _ => None,
+        }
+    }
+
+    /// Returns the error that caused this one, without the context added on top of it.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Stream { source, .. } | Error::File { source, .. } | Error::Internal(source) => {
+                source.root_cause()
+            }
+            e => e,
+        }
+    }
+}
+
+/// An error of ved that went through an I/O interface, like the one of a reader giving up on its
+/// stream, is taken back out.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        if !e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::IoError(e);
+        }
+        match e.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(e)) => *e,
+            _ => unreachable!("the inner error is an Error"),
+        }
+    }
 }
 
 impl From<Box<dyn Any + Send + 'static>> for Error {
@@ -37,3 +160,34 @@ impl From<Box<dyn Any + Send + 'static>> for Error {
         Error::ThreadPanic("A thread panicked with an unprintable payload.".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_file() {
+        let e = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        let stream = Error::Stream {
+            offset: 12,
+            source: Box::new(e.into()),
+        };
+        let e = stream.in_file(Path::new("a.txt"), Operation::Rewrite);
+        assert_eq!(
+            e.to_string(),
+            "cannot rewrite a.txt at byte 12: IO error: eof"
+        );
+        assert_eq!(e.path(), Some(Path::new("a.txt")));
+        assert!(matches!(e.root_cause(), Error::IoError(_)));
+        let e = Error::NothingToDo.in_file(Path::new("b"), Operation::Open);
+        assert_eq!(e.to_string(), "cannot open b: nothing to search or edit");
+    }
+
+    #[test]
+    fn test_from_io() {
+        let e = Error::from(io::Error::other(Error::Cancelled));
+        assert!(matches!(e, Error::Cancelled));
+        let e = Error::from(io::Error::other("disk full"));
+        assert!(matches!(e, Error::IoError(_)));
+    }
+}
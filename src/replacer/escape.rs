@@ -0,0 +1,90 @@
+/// Returns the bytes of a text where `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` are escapes.
+///
+/// The escapes make it possible to write any byte, including the ones that are not valid UTF-8.
+pub fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let (&escape, after) = rest
+            .split_first()
+            .ok_or_else(|| format!("unfinished escape at the end of {text:?}"))?;
+        rest = after;
+        let byte = match escape {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'0' => b'\0',
+            b'\\' => b'\\',
+            b'x' if rest.len() >= 2 => {
+                let byte = hex_byte(&rest[..2])
+                    .ok_or_else(|| format!("invalid escape \\x{} in {text:?}", shown(rest)))?;
+                rest = &rest[2..];
+                byte
+            }
+            b'x' => return Err(format!("unfinished escape \\x in {text:?}")),
+            _ => return Err(format!("unknown escape \\{} in {text:?}", escape as char)),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+/// Returns the bytes written in hexadecimal, two digits per byte, like `DEADBEEF`.
+///
+/// The whitespace between the bytes is ignored, so that `de ad be ef` is the same.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hexadecimal digits in {text:?}"));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            hex_byte(pair).ok_or_else(|| format!("invalid hexadecimal byte {:?}", shown(pair)))
+        })
+        .collect()
+}
+
+/// Returns the byte written with the two hexadecimal digits of `pair`.
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    let digit = |d: u8| (d as char).to_digit(16);
+    Some((digit(pair[0])? * 16 + digit(pair[1])?) as u8)
+}
+
+/// Returns the first two bytes of `bytes` as text, for error messages.
+fn shown(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&bytes[..bytes.len().min(2)]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("abba"), Ok(b"abba".to_vec()));
+        assert_eq!(unescape(r"a\nb\tc\r\0"), Ok(b"a\nb\tc\r\0".to_vec()));
+        assert_eq!(
+            unescape(r"\x00\xffé\\x"),
+            Ok(b"\x00\xff\xc3\xa9\\x".to_vec())
+        );
+        assert!(unescape(r"a\").is_err());
+        assert!(unescape(r"\x4").is_err());
+        assert!(unescape(r"\xzz").is_err());
+        assert!(unescape(r"\q").is_err());
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("DEADbeef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_hex("00 ff\n7F"), Ok(vec![0x00, 0xff, 0x7f]));
+        assert_eq!(parse_hex(""), Ok(Vec::new()));
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use crate::replacer::lines::{Line, LineWindow};
+use crate::replacer::separator::Separator;
+use std::io::{BufRead, Write};
+
+/// The number of lines shown before and after the line of a match.
+const CONTEXT_LINES: usize = 2;
+
+/// What the user wants to do with a match.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Answer {
+    /// Replace this match
+    Yes,
+    /// Skip this match
+    No,
+    /// Replace this match and all the remaining ones in the file
+    All,
+    /// Skip this match and all the remaining ones in the file
+    SkipFile,
+    /// Skip this match and stop processing files
+    Quit,
+}
+
+/// Asks the user what to do with each match, like `:s///c` in vim.
+///
+/// The answers are read from `input` so that a Prompt can be driven by something else than a TTY.
+pub struct Prompt<In, Out>
+where
+    In: BufRead,
+    Out: Write,
+{
+    input: In,
+    output: Out,
+    quit: bool,
+}
+
+impl<In, Out> Prompt<In, Out>
+where
+    In: BufRead,
+    Out: Write,
+{
+    pub fn new(input: In, output: Out) -> Self {
+        Self {
+            input,
+            output,
+            quit: false,
+        }
+    }
+
+    /// Whether the user asked to stop processing files.
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    fn ask(&mut self, name: &str, lines: &[Line], target: usize, diff: &Diff) -> Result<Answer> {
+        let width = lines.last().map_or(1, |l| l.number.to_string().len());
+        writeln!(self.output, "{name}:")?;
+        for (i, line) in lines.iter().enumerate() {
+            let text = String::from_utf8_lossy(&line.text);
+            writeln!(self.output, "{:>width$} | {text}", line.number)?;
+            if i == target {
+                let column = diff.pos - line.start;
+                let len = diff
+                    .remove
+                    .clamp(1, line.text.len().saturating_sub(column).max(1));
+                let padding = " ".repeat(width + 3 + column);
+                writeln!(self.output, "{padding}{}", "^".repeat(len))?;
+            }
+        }
+        loop {
+            write!(
+                self.output,
+                "replace with {:?}? [y]es [n]o [a]ll [s]kip file [q]uit: ",
+                String::from_utf8_lossy(&diff.add)
+            )?;
+            self.output.flush()?;
+            let mut answer = String::new();
+            if self.input.read_line(&mut answer)? == 0 {
+                self.quit = true;
+                return Ok(Answer::Quit);
+            }
+            let answer = match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => Answer::Yes,
+                "n" | "no" => Answer::No,
+                "a" | "all" => Answer::All,
+                "s" | "skip" => Answer::SkipFile,
+                "q" | "quit" => Answer::Quit,
+                _ => continue,
+            };
+            if answer == Answer::Quit {
+                self.quit = true;
+            }
+            return Ok(answer);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Ask,
+    ReplaceAll,
+    SkipAll,
+}
+
+/// Filters the Diffs of a file according to the answers given to a Prompt.
+pub struct Confirm<'search, 'iterator, 'prompt, R, In, Out>
+where
+    R: BufRead,
+    In: BufRead,
+    Out: Write,
+    'search: 'iterator,
+{
+    diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+    lines: LineWindow<R>,
+    name: String,
+    prompt: &'prompt mut Prompt<In, Out>,
+    mode: Mode,
+}
+
+impl<'search, 'iterator, 'prompt, R, In, Out> Confirm<'search, 'iterator, 'prompt, R, In, Out>
+where
+    R: BufRead,
+    In: BufRead,
+    Out: Write,
+    'search: 'iterator,
+{
+    /// Creates a filter over `diffs`.
+    ///
+    /// `original` must read the same content as the one the diffs were found in, it is used to
+    /// show the lines, ended by `separator`, around each match.
+    pub fn new(
+        diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+        original: R,
+        separator: Separator,
+        name: String,
+        prompt: &'prompt mut Prompt<In, Out>,
+    ) -> Self {
+        Self {
+            diffs,
+            lines: LineWindow::new(original, CONTEXT_LINES, separator),
+            name,
+            prompt,
+            mode: Mode::Ask,
+        }
+    }
+
+    fn next_confirmed(&mut self) -> Result<Option<Diff<'search>>> {
+        loop {
+            if self.mode == Mode::SkipAll {
+                return Ok(None);
+            }
+            let diff = match self.diffs.next() {
+                None => return Ok(None),
+                Some(diff) => diff?,
+            };
+            if self.mode == Mode::ReplaceAll {
+                return Ok(Some(diff));
+            }
+            let (lines, target) = self.lines.around(diff.pos)?;
+            match self.prompt.ask(&self.name, lines, target, &diff)? {
+                Answer::Yes => return Ok(Some(diff)),
+                Answer::No => {}
+                Answer::All => {
+                    self.mode = Mode::ReplaceAll;
+                    return Ok(Some(diff));
+                }
+                Answer::SkipFile | Answer::Quit => self.mode = Mode::SkipAll,
+            }
+        }
+    }
+}
+
+impl<'search, 'iterator, 'prompt, R, In, Out> Iterator
+    for Confirm<'search, 'iterator, 'prompt, R, In, Out>
+where
+    R: BufRead,
+    In: BufRead,
+    Out: Write,
+    'search: 'iterator,
+{
+    type Item = Result<Diff<'search>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'search>>> {
+        self.next_confirmed().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CONTENT: &str = "abba\nl2 abba\nl3\nabba";
+
+    fn diffs() -> Vec<Result<Diff<'static>>> {
+        [0, 8, 16]
+            .into_iter()
+            .map(|pos| {
+                Ok(Diff {
+                    pos,
+                    remove: 4,
+                    add: "toto".as_bytes().into(),
+                    anchor: pos,
+                    row: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn confirmed(answers: &str) -> (Vec<usize>, String, bool) {
+        let mut prompt = Prompt::new(Cursor::new(answers), Vec::new());
+        let positions = {
+            let confirm = Confirm::new(
+                Box::new(diffs().into_iter()),
+                Cursor::new(CONTENT),
+                Separator::default(),
+                "file".to_string(),
+                &mut prompt,
+            );
+            confirm.map(|d| d.unwrap().pos).collect()
+        };
+        let quit = prompt.has_quit();
+        let output = String::from_utf8(prompt.output).unwrap();
+        (positions, output, quit)
+    }
+
+    #[test]
+    fn test_yes_and_no() {
+        let (positions, _, quit) = confirmed("y\nn\ny\n");
+        assert_eq!(positions, vec![0, 16]);
+        assert!(!quit);
+    }
+
+    #[test]
+    fn test_all() {
+        let (positions, output, _) = confirmed("n\na\n");
+        assert_eq!(positions, vec![8, 16]);
+        assert_eq!(output.matches("replace with").count(), 2);
+    }
+
+    #[test]
+    fn test_skip_file() {
+        let (positions, _, quit) = confirmed("y\ns\n");
+        assert_eq!(positions, vec![0]);
+        assert!(!quit);
+    }
+
+    #[test]
+    fn test_quit() {
+        let (positions, _, quit) = confirmed("q\n");
+        assert!(positions.is_empty());
+        assert!(quit);
+    }
+
+    #[test]
+    fn test_end_of_input_quits() {
+        let (positions, _, quit) = confirmed("y\n");
+        assert_eq!(positions, vec![0]);
+        assert!(quit);
+    }
+
+    #[test]
+    fn test_invalid_answer_asks_again() {
+        let (positions, output, _) = confirmed("maybe\ny\nn\nn\n");
+        assert_eq!(positions, vec![0]);
+        assert_eq!(output.matches("replace with").count(), 4);
+    }
+
+    #[test]
+    fn test_context_and_highlight() {
+        let (_, output, _) = confirmed("n\nq\n");
+        let expected = "file:\n\
+                        1 | abba\n\
+                        \x20   ^^^^\n\
+                        2 | l2 abba\n\
+                        3 | l3\n\
+                        replace with \"toto\"? [y]es [n]o [a]ll [s]kip file [q]uit: \
+                        file:\n\
+                        1 | abba\n\
+                        2 | l2 abba\n\
+                        \x20      ^^^^\n\
+                        3 | l3\n\
+                        4 | abba\n\
+                        replace with \"toto\"? [y]es [n]o [a]ll [s]kip file [q]uit: ";
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::error::Result;
+use crate::replacer::separator::{Separator, SeparatorFinder};
+use std::collections::VecDeque;
+use std::io::{BufRead, Read};
+
+/// A line of a stream, without its separator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    /// The offset of the first byte of the line with the start of the stream
+    pub start: usize,
+    /// The number of the line, starting at 1
+    pub number: usize,
+    pub text: Vec<u8>,
+}
+
+/// The number of bytes at the end of a chunk that may start a character going on in the next one.
+const MAX_CHAR_LEN: usize = 4;
+
+/// Counts the lines of a stream up to increasing positions.
+pub struct LineCounter<R>
+where
+    R: Read,
+{
+    reader: R,
+    separators: SeparatorFinder,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+    buffer: Vec<u8>,
+    /// How the columns are counted, they are counted in bytes without it
+    columns: Option<SearchConfig>,
+    /// The column reached by the bytes counted, starting at 0
+    column: usize,
+    /// The last bytes read, that were not counted yet because they may start a character or a
+    /// separator going on in the next chunk
+    uncounted: Vec<u8>,
+}
+
+impl<R> LineCounter<R>
+where
+    R: Read,
+{
+    /// Creates a counter of the lines of `reader` ended by `separator`.
+    pub fn new(reader: R, separator: Separator) -> Self {
+        Self {
+            reader,
+            separators: SeparatorFinder::new(separator),
+            pos: 0,
+            line: 1,
+            line_start: 0,
+            buffer: vec![0; 64 * 1024],
+            columns: None,
+            column: 0,
+            uncounted: Vec::new(),
+        }
+    }
+
+    /// Creates a counter of the lines of `reader`, whose columns are counted like the ones of a
+    /// block are with `config`.
+    pub fn with_columns(reader: R, config: SearchConfig) -> Self {
+        Self {
+            columns: Some(config.clone()),
+            ..Self::new(reader, config.separator)
+        }
+    }
+
+    /// Returns the line and the column of `pos`, both starting at 1.
+    ///
+    /// `pos` must not be lower than the one of the previous call.
+    pub fn locate(&mut self, pos: usize) -> Result<(usize, usize)> {
+        self.advance_to(pos, None)?;
+        let column = match self.columns {
+            None => pos - self.line_start,
+            Some(_) => self.column,
+        };
+        Ok((self.line, column + 1))
+    }
+
+    /// Reads the `len` bytes following the last position located, and returns them.
+    pub fn take(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(len);
+        self.advance_to(self.pos + len, Some(&mut bytes))?;
+        Ok(bytes)
+    }
+
+    /// Reads the stream up to `target`, counting the separators on the way, and adds the bytes
+    /// read to `read`.
+    fn advance_to(&mut self, target: usize, mut read: Option<&mut Vec<u8>>) -> Result<()> {
+        while self.pos < target {
+            let chunk_size = std::cmp::min(target - self.pos, self.buffer.len());
+            let chunk = &mut self.buffer[..chunk_size];
+            self.reader.read_exact(chunk)?;
+            if let Some(read) = read.as_mut() {
+                read.extend_from_slice(chunk);
+            }
+            if let Some(config) = &self.columns {
+                let last = self.pos + chunk_size == target;
+                let mut bytes = std::mem::take(&mut self.uncounted);
+                bytes.extend_from_slice(chunk);
+                let held = match last {
+                    true => 0,
+                    false => MAX_CHAR_LEN.max(config.separator.len()) - 1,
+                };
+                let limit = bytes.len().saturating_sub(held);
+                let (column, counted) = config.advance_until(self.column, &bytes, limit);
+                self.column = column;
+                self.uncounted = bytes[counted.min(bytes.len())..].to_vec();
+            }
+            let chunk = &self.buffer[..chunk_size];
+            let mut rest = chunk;
+            while let Some(end) = self.separators.find_end(rest) {
+                self.line += 1;
+                self.line_start = self.pos + end;
+                self.pos += end;
+                rest = &rest[end..];
+            }
+            self.separators.skip(rest);
+            self.pos += rest.len();
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the lines of a stream that surround a position.
+///
+/// Positions must be requested in increasing order: the lines that are too far behind the last
+/// requested position are dropped, so that memory does not grow with the size of the stream.
+pub struct LineWindow<R>
+where
+    R: BufRead,
+{
+    reader: R,
+    separator: Separator,
+    lines: VecDeque<Line>,
+    context: usize,
+    next_start: usize,
+    next_number: usize,
+    eof: bool,
+}
+
+impl<R> LineWindow<R>
+where
+    R: BufRead,
+{
+    /// Creates a window that keeps `context` lines before and after the requested line, the lines
+    /// being ended by `separator`.
+    pub fn new(reader: R, context: usize, separator: Separator) -> Self {
+        Self {
+            reader,
+            separator,
+            lines: VecDeque::new(),
+            context,
+            next_start: 0,
+            next_number: 1,
+            eof: false,
+        }
+    }
+
+    /// Returns the lines around the one containing `pos`, and the index of that line.
+    pub fn around(&mut self, pos: usize) -> Result<(&[Line], usize)> {
+        while !self.eof
+            && self
+                .lines_after(pos)
+                .is_none_or(|after| after < self.context)
+        {
+            self.read_line()?;
+        }
+        while self.lines_before(pos) > self.context {
+            self.lines.pop_front();
+        }
+        let target = self.lines_before(pos);
+        Ok((self.lines.make_contiguous(), target))
+    }
+
+    fn lines_before(&self, pos: usize) -> usize {
+        self.lines
+            .iter()
+            .take_while(|line| line.start + line.text.len() < pos)
+            .count()
+    }
+
+    /// Returns the number of lines after the one containing `pos`, if that one was read already.
+    fn lines_after(&self, pos: usize) -> Option<usize> {
+        match self.lines.back() {
+            Some(line) if line.start + line.text.len() >= pos => {
+                Some(self.lines.iter().filter(|line| line.start > pos).count())
+            }
+            _ => None,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<()> {
+        let mut text = Vec::new();
+        let last_byte = self.separator.last_byte();
+        while self.reader.read_until(last_byte, &mut text)? > 0 && !self.separator.ends(&text) {}
+        let nb_read = text.len();
+        if nb_read == 0 {
+            self.eof = true;
+            return Ok(());
+        }
+        if self.separator.ends(&text) {
+            text.truncate(nb_read - self.separator.len());
+        } else {
+            self.eof = true;
+        }
+        self.lines.push_back(Line {
+            start: self.next_start,
+            number: self.next_number,
+            text,
+        });
+        self.next_start += nb_read;
+        self.next_number += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replacer::columns::ColumnMode;
+    use std::io::{BufReader, Cursor};
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_locate() {
+        let mut counter =
+            LineCounter::new(Cursor::new("abba\n_abba\n\n__abba"), Separator::default());
+        assert_eq!(counter.locate(0).unwrap(), (1, 1));
+        assert_eq!(counter.locate(3).unwrap(), (1, 4));
+        assert_eq!(counter.locate(6).unwrap(), (2, 2));
+        assert_eq!(counter.locate(14).unwrap(), (4, 3));
+    }
+
+    #[test]
+    fn test_locate_columns() {
+        let config = SearchConfig {
+            columns: ColumnMode::Width,
+            tabstop: NonZeroUsize::new(4),
+            separator: "crlf".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        let content = "a\tb\r\n\u{1F680}é\tx";
+        let mut counter = LineCounter::with_columns(Cursor::new(content), config);
+        // A buffer of 3 bytes splits the separator and the characters between reads
+        counter.buffer.truncate(3);
+        assert_eq!(counter.locate(2).unwrap(), (1, 5));
+        assert_eq!(counter.take(1).unwrap(), b"b");
+        assert_eq!(counter.locate(5).unwrap(), (2, 1));
+        assert_eq!(counter.locate(9).unwrap(), (2, 3));
+        assert_eq!(counter.take(2).unwrap(), "é".as_bytes());
+        assert_eq!(counter.locate(12).unwrap(), (2, 5));
+    }
+
+    #[test]
+    fn test_locate_separator_across_reads() {
+        let content = "ab\r\ncd\r\nef";
+        let separator: Separator = "crlf".parse().unwrap();
+        let mut counter = LineCounter::new(Cursor::new(content), separator.clone());
+        // A buffer of 3 bytes splits the first separator between two reads
+        counter.buffer.truncate(3);
+        assert_eq!(counter.locate(1).unwrap(), (1, 2));
+        assert_eq!(counter.locate(5).unwrap(), (2, 2));
+        assert_eq!(counter.locate(8).unwrap(), (3, 1));
+        let mut counter = LineCounter::new(Cursor::new("a\nb\0c"), "nul".parse().unwrap());
+        assert_eq!(counter.locate(5).unwrap(), (2, 2));
+    }
+
+    #[test]
+    fn test_window_separator() {
+        let reader = BufReader::with_capacity(3, Cursor::new("l1\r\nl2\r\nl3"));
+        let mut window = LineWindow::new(reader, 1, "crlf".parse().unwrap());
+        let (lines, target) = window.around(4).unwrap();
+        assert_eq!(numbers(lines), vec![1, 2, 3]);
+        assert_eq!(lines[target].text, b"l2");
+        assert_eq!(lines[target].start, 4);
+    }
+
+    fn numbers(lines: &[Line]) -> Vec<usize> {
+        lines.iter().map(|l| l.number).collect()
+    }
+
+    #[test]
+    fn test_single_line() {
+        let mut window = LineWindow::new(Cursor::new("abba"), 1, Separator::default());
+        let (lines, target) = window.around(2).unwrap();
+        assert_eq!(numbers(lines), vec![1]);
+        assert_eq!(lines[target].text, b"abba");
+    }
+
+    #[test]
+    fn test_context() {
+        let mut window =
+            LineWindow::new(Cursor::new("l1\nl2\nl3\nl4\nl5\n"), 1, Separator::default());
+        let (lines, target) = window.around(6).unwrap();
+        assert_eq!(numbers(lines), vec![2, 3, 4]);
+        assert_eq!(lines[target].text, b"l3");
+        assert_eq!(lines[target].start, 6);
+
+        let (lines, target) = window.around(13).unwrap();
+        assert_eq!(numbers(lines), vec![4, 5]);
+        assert_eq!(lines[target].text, b"l5");
+    }
+
+    #[test]
+    fn test_same_line_twice() {
+        let mut window = LineWindow::new(Cursor::new("abba abba\nl2"), 0, Separator::default());
+        let (lines, target) = window.around(0).unwrap();
+        assert_eq!(lines[target].number, 1);
+        let (lines, target) = window.around(5).unwrap();
+        assert_eq!(numbers(lines), vec![1]);
+        assert_eq!(lines[target].number, 1);
+    }
+
+    #[test]
+    fn test_match_at_line_start() {
+        let mut window = LineWindow::new(Cursor::new("l1\nabba\nl3"), 0, Separator::default());
+        let (lines, target) = window.around(3).unwrap();
+        assert_eq!(numbers(lines), vec![2]);
+        assert_eq!(lines[target].text, b"abba");
+    }
+}
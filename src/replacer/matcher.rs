@@ -0,0 +1,210 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use std::collections::VecDeque;
+use std::io::Read;
+
+/// A search engine finding the occurrences to replace in a stream, window after window.
+///
+/// The stream is handed to the matcher as a window of the bytes read so far that it is not done
+/// with yet. The window grows while the matcher needs more bytes to decide, up to the window size
+/// of the search config; the occurrences that do not fit in it are not found.
+pub trait Matcher {
+    /// Finds the occurrences in `window`, whose first byte is at `pos` in the stream.
+    ///
+    /// `eof` tells whether the window goes up to the end of the stream. The diffs returned must
+    /// be sorted, must not overlap and must be inside the part of the window the matcher is done
+    /// with; replacing fails with `Error::UnorderedMatch` otherwise.
+    fn find(&self, window: &[u8], pos: usize, eof: bool) -> Found<'_>;
+
+    /// Returns the number of rows of the occurrences, 1 unless they are blocks.
+    ///
+    /// The diffs of the rows of an occurrence follow each other, from its first row.
+    fn nb_rows(&self) -> usize {
+        1
+    }
+}
+
+/// What the stream functions search with: anything giving the occurrences of a whole stream.
+///
+/// Every Matcher is a Search, its `find` being handed the stream window after window. A search
+/// that reads the stream in its own way, like a `SearchSpec`, implements this trait directly.
+pub trait Search {
+    /// Returns the number of rows of the occurrences, 1 unless they are blocks.
+    fn nb_rows(&self) -> usize;
+
+    /// Returns the occurrences of a whole stream, sorted and not overlapping.
+    fn diffs<'a>(
+        &'a self,
+        config: &SearchConfig,
+        reader: Box<dyn Read + 'a>,
+    ) -> Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a>;
+}
+
+impl<M: Matcher + ?Sized> Search for M {
+    fn nb_rows(&self) -> usize {
+        Matcher::nb_rows(self)
+    }
+
+    fn diffs<'a>(
+        &'a self,
+        config: &SearchConfig,
+        reader: Box<dyn Read + 'a>,
+    ) -> Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a> {
+        Box::new(MatcherDiffs::new(self, config.window(), reader))
+    }
+}
+
+/// The occurrences found by a Matcher in a window.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Found<'search> {
+    pub diffs: Vec<Diff<'search>>,
+    /// The number of bytes at the start of the window that the matcher is done with
+    ///
+    /// They are not passed again to the matcher. At the end of the stream, the whole window is
+    /// done with, whatever this number.
+    pub consumed: usize,
+}
+
+/// The size of the reads feeding the window of a Matcher.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Produces the Diffs of a Matcher over a stream.
+pub struct MatcherDiffs<'search, M, R>
+where
+    M: Matcher + ?Sized,
+    R: Read,
+{
+    matcher: &'search M,
+    reader: R,
+    window: Vec<u8>,
+    max_window: usize,
+    /// The position of the first byte of the window in the stream
+    pos: usize,
+    eof: bool,
+    ready: VecDeque<Diff<'search>>,
+}
+
+impl<'search, M, R> MatcherDiffs<'search, M, R>
+where
+    M: Matcher + ?Sized,
+    R: Read,
+{
+    pub fn new(matcher: &'search M, max_window: usize, reader: R) -> Self {
+        Self {
+            matcher,
+            reader,
+            window: Vec::new(),
+            max_window,
+            pos: 0,
+            eof: false,
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn next_diff(&mut self) -> Result<Option<Diff<'search>>> {
+        while self.ready.is_empty() {
+            if self.eof && self.window.is_empty() {
+                return Ok(None);
+            }
+            self.fill_window()?;
+            let found = self.matcher.find(&self.window, self.pos, self.eof);
+            self.ready.extend(found.diffs);
+            let full = self.window.len() >= self.max_window;
+            let consumed = match found.consumed {
+                _ if self.eof => self.window.len(),
+                // the window cannot grow, the matcher has to let go of its start
+                0 if full => self.window.len(),
+                consumed => consumed.min(self.window.len()),
+            };
+            self.window.drain(..consumed);
+            self.pos += consumed;
+        }
+        Ok(self.ready.pop_front())
+    }
+
+    /// Reads a chunk at the end of the window, within the size limit of the window.
+    fn fill_window(&mut self) -> Result<()> {
+        let wanted = CHUNK_SIZE.min(self.max_window.saturating_sub(self.window.len()));
+        let start = self.window.len();
+        self.window.resize(start + wanted, 0);
+        let mut filled = 0;
+        while filled < wanted && !self.eof {
+            let nb_read = self.reader.read(&mut self.window[start + filled..])?;
+            self.eof = nb_read == 0;
+            filled += nb_read;
+        }
+        self.window.truncate(start + filled);
+        Ok(())
+    }
+}
+
+impl<'search, M, R> Iterator for MatcherDiffs<'search, M, R>
+where
+    M: Matcher + ?Sized,
+    R: Read,
+{
+    type Item = Result<Diff<'search>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'search>>> {
+        self.next_diff().transpose()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Replaces the whole words of ASCII letters, not the ones that are part of a longer word.
+    pub(crate) struct WordMatcher {
+        pub(crate) word: &'static str,
+        pub(crate) replacement: &'static str,
+    }
+
+    impl Matcher for WordMatcher {
+        fn find(&self, window: &[u8], pos: usize, eof: bool) -> Found<'_> {
+            let mut found = Found::default();
+            let mut start = 0;
+            while start < window.len() {
+                let len = window[start..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphabetic())
+                    .count();
+                if start + len == window.len() && !eof {
+                    // the word may go on after the window
+                    break;
+                }
+                if &window[start..start + len] == self.word.as_bytes() {
+                    found.diffs.push(Diff {
+                        pos: pos + start,
+                        remove: len,
+                        add: self.replacement.as_bytes().into(),
+                        anchor: pos + start,
+                        row: 0,
+                    });
+                }
+                start += len.max(1);
+                found.consumed = start;
+            }
+            found
+        }
+    }
+
+    #[test]
+    fn test_word_matcher() {
+        let matcher = WordMatcher {
+            word: "abba",
+            replacement: "toto",
+        };
+        let content = "abba abbas abba, xabba abba";
+        for max_window in [5, 7, 100] {
+            let diffs = MatcherDiffs::new(&matcher, max_window, Cursor::new(content));
+            let positions: Vec<_> = diffs.map(|d| d.unwrap().pos).collect();
+            assert_eq!(positions, vec![0, 11, 23], "window of {max_window}");
+        }
+        // the words that do not fit in the window are not found
+        let diffs = MatcherDiffs::new(&matcher, 3, Cursor::new(content));
+        assert_eq!(diffs.count(), 0);
+    }
+}
@@ -1,114 +1,760 @@
+mod address;
 mod bufsearcher;
+mod builder;
+mod cancel;
+mod checker;
+mod columns;
+mod compare;
+mod computed;
 mod diff;
 mod diffheap;
 mod error;
+mod escape;
+mod interactive;
+mod lines;
+mod matcher;
+mod options;
+mod pool;
+mod progress;
+mod rectangle;
+mod report;
+mod rows;
+mod select;
+mod separator;
+mod spec;
+mod walker;
 
 use crate::teereader;
-use bufsearcher::BufSearcher;
-use diff::Diff;
-use error::{Error, Result};
+pub use address::Address;
+pub use bufsearcher::SearchConfig;
+pub use builder::Ved;
+pub use cancel::CancelToken;
+use cancel::Interrupt;
+use checker::Checker;
+pub use checker::Hit;
+pub use columns::{vertical, Align, ColumnMode};
+use compare::Compare;
+use computed::Computed;
+pub use computed::MatchInfo;
+pub use diff::Diff;
+pub use error::{Error, Operation, Result};
+pub use escape::{parse_hex, unescape};
 use glob;
+use interactive::Confirm;
+pub use interactive::Prompt;
+pub use matcher::{Found, Matcher, Search};
+pub use options::Options;
+pub use pool::ErrorPolicy;
+use pool::WorkerPool;
+use progress::Reported;
+pub use progress::{Observer, Progress};
 use rand::Rng;
+use rectangle::RectangleDiffs;
+pub use rectangle::{ColumnRange, Rectangle};
+pub use report::{FileReport, FileStats, Report, SkipReason, Totals};
+pub use rows::NumberedRule;
+use select::Select;
+pub use select::{Counts, Selection};
+pub use separator::Separator;
+pub use spec::SearchSpec;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Seek, Write};
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::iter::Peekable;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::thread;
-use std::time::{Duration, Instant};
-
-// TODO change Vec to slice
-pub fn replace_glob<'search>(
-    patterns: &'search Vec<&'search str>,
-    replacements: &'search Vec<&'search str>,
-    file_glob: &'search str,
-) -> Result<Vec<Result<PathBuf>>> {
-    let paths = glob::glob(file_glob)?;
-
-    let results = thread::scope(|scope| {
-        let handles: Vec<_> = paths
-            .map(|glob_path| {
-                scope.spawn(|| {
-                    let path = match glob_path {
-                        Ok(p) => p,
-                        Err(e) => return Err(e.into()),
-                    };
-                    if !path.as_path().is_dir() {
-                        match replace_path(patterns, replacements, &path) {
-                            Ok(_) => Ok(path),
-                            Err(e) => Err(e),
-                        }
-                    } else {
-                        Ok(path)
-                    }
-                })
-            })
-            .collect();
-        handles.into_iter().map(|handle| handle.join()?).collect()
-    });
-    Ok(results)
+use std::sync::atomic::AtomicUsize;
+use std::sync::Mutex;
+use std::time::Instant;
+use walker::DirWalker;
+
+// Search and replace the occurrences found by a matcher, like a spec, in the files matching a
+// glob.
+pub fn replace_glob<M>(matcher: &M, file_glob: &str, options: &Options) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+{
+    let budget = options.selection.budget();
+    run_files(pool(options), glob_files(file_glob)?, |path| {
+        replace_file(matcher, path, options, budget.as_ref())
+    })
+}
+
+// Search and replace the occurrences found by a matcher, like a spec, in a file or recursively in
+// a directory.
+//
+// The files of a directory are processed in parallel by the same worker pool as globs.
+// Under `ErrorPolicy::Continue`, the report has every file and every error; under
+// `ErrorPolicy::FailFast`, the first error is returned instead.
+pub fn replace_path<M>(matcher: &M, path: &Path, options: &Options) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+{
+    let paths = DirWalker::new(path.to_owned());
+    let budget = options.selection.budget();
+    run_files(pool(options), paths, |path| {
+        replace_file(matcher, path, options, budget.as_ref())
+    })
 }
 
-// Search and replace a pattern in a file or recursively in a directory.
+// Like `replace_glob`, but the replacements are computed by `replace` for each occurrence.
+//
+// The replacements of the matcher are not used.
+pub fn replace_glob_fn<M, F>(
+    matcher: &M,
+    replace: &F,
+    file_glob: &str,
+    options: &Options,
+) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]> + Sync,
+{
+    let budget = options.selection.budget();
+    run_files(pool(options), glob_files(file_glob)?, |path| {
+        rewrite_file(path, options.progress.as_deref(), |input, output| {
+            replace_stream_fn_budgeted(matcher, replace, input, output, options, budget.as_ref())
+        })
+    })
+}
+
+// Like `replace_path`, but the replacements are computed by `replace` for each occurrence.
+pub fn replace_path_fn<M, F>(
+    matcher: &M,
+    replace: &F,
+    path: &Path,
+    options: &Options,
+) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]> + Sync,
+{
+    let paths = DirWalker::new(path.to_owned());
+    let budget = options.selection.budget();
+    run_files(pool(options), paths, |path| {
+        rewrite_file(path, options.progress.as_deref(), |input, output| {
+            replace_stream_fn_budgeted(matcher, replace, input, output, options, budget.as_ref())
+        })
+    })
+}
+
+// Like `replace_glob`, but each match is only replaced if the user accepts it.
+pub fn replace_glob_interactive<In, Out>(
+    spec: &SearchSpec,
+    file_glob: &str,
+    options: &Options,
+    prompt: &mut Prompt<In, Out>,
+) -> Result<Report>
+where
+    In: BufRead + Send,
+    Out: Write + Send,
+{
+    confirm_files(spec, glob_files(file_glob)?, options, prompt)
+}
+
+// Like `replace_path`, but each match is only replaced if the user accepts it.
+pub fn replace_path_interactive<In, Out>(
+    spec: &SearchSpec,
+    path: &Path,
+    options: &Options,
+    prompt: &mut Prompt<In, Out>,
+) -> Result<Report>
+where
+    In: BufRead + Send,
+    Out: Write + Send,
+{
+    confirm_files(spec, DirWalker::new(path.to_owned()), options, prompt)
+}
+
+/// Replaces in the files of `paths`, each match only if the user accepts it.
+///
+/// Files are processed one after the other so that the prompts do not interleave.
+fn confirm_files<I, In, Out>(
+    spec: &SearchSpec,
+    paths: I,
+    options: &Options,
+    prompt: &mut Prompt<In, Out>,
+) -> Result<Report>
+where
+    I: Iterator<Item = Result<PathBuf>> + Send,
+    In: BufRead + Send,
+    Out: Write + Send,
+{
+    let budget = options.selection.budget();
+    let prompt = Mutex::new(prompt);
+    let pool = WorkerPool::with_workers(1, options.policy).cancellable(options.cancel.clone());
+    run_files(pool, paths, |path| {
+        let budget = budget.as_ref();
+        replace_file_interactive(spec, path, options, budget, &prompt)
+    })
+}
+
+// Search the occurrences found by a matcher, like a spec, in the files matching a glob without
+// modifying them.
+//
+// The report has every file along with the places where the occurrences were found.
+//
+// The replacements of the occurrences are not used.
+pub fn check_glob<M>(matcher: &M, file_glob: &str, options: &Options) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+{
+    let budget = options.selection.budget();
+    run_files(pool(options), glob_files(file_glob)?, |path| {
+        check_file(matcher, path, options, budget.as_ref())
+    })
+}
+
+// Search the occurrences found by a matcher in a file or recursively in a directory without
+// modifying anything.
+pub fn check_path<M>(matcher: &M, path: &Path, options: &Options) -> Result<Report>
+where
+    M: Search + Sync + ?Sized,
+{
+    let paths = DirWalker::new(path.to_owned());
+    let budget = options.selection.budget();
+    run_files(pool(options), paths, |path| {
+        check_file(matcher, path, options, budget.as_ref())
+    })
+}
+
+// Apply a rectangle edit to every line of the files matching a glob.
+pub fn edit_glob(rectangle: &Rectangle, file_glob: &str, options: &Options) -> Result<Report> {
+    let budget = options.selection.budget();
+    run_files(pool(options), glob_files(file_glob)?, |path| {
+        edit_file(rectangle, path, options, budget.as_ref())
+    })
+}
+
+// Apply a rectangle edit to every line of a file or, recursively, of a directory.
+pub fn edit_path(rectangle: &Rectangle, path: &Path, options: &Options) -> Result<Report> {
+    let paths = DirWalker::new(path.to_owned());
+    let budget = options.selection.budget();
+    run_files(pool(options), paths, |path| {
+        edit_file(rectangle, path, options, budget.as_ref())
+    })
+}
+
+/// Returns the pool processing the files of a run.
+///
+/// With a global limit on the number of occurrences, the files are processed one after the other
+/// so that the occurrences kept are the first ones in the order of the files.
+fn pool(options: &Options) -> WorkerPool {
+    let pool = if options.selection.max_total.is_some() {
+        WorkerPool::with_workers(1, options.policy)
+    } else {
+        WorkerPool::new(options.policy)
+    };
+    pool.cancellable(options.cancel.clone())
+}
+
+/// Processes the files with a pool, and gathers what was done to each of them in a report.
+fn run_files<I, F>(pool: WorkerPool, paths: I, work: F) -> Result<Report>
+where
+    I: Iterator<Item = Result<PathBuf>> + Send,
+    F: Fn(&Path) -> Result<FileReport> + Sync,
+{
+    let start = Instant::now();
+    let results = pool.run(paths, work)?;
+    Ok(Report::new(results, start.elapsed()))
+}
+
+fn glob_files(file_glob: &str) -> Result<impl Iterator<Item = Result<PathBuf>> + Send> {
+    let paths = glob::glob(file_glob)?
+        .filter(|glob_path| !matches!(glob_path, Ok(p) if p.is_dir()))
+        .map(|glob_path| glob_path.map_err(Error::from));
+    Ok(paths)
+}
+
+fn check_file<M>(
+    matcher: &M,
+    path: &Path,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<FileReport>
+where
+    M: Search + ?Sized,
+{
+    inspect_file(path, options.progress.as_deref(), |input| {
+        check_stream_budgeted(matcher, input, options, budget)
+    })
+}
+
+// Search and replace the occurrences found by a matcher in a single file.
 //
 // For each file that must change, the result of the replacement is first
 // written into a temporary file and the original file is replaced by the
 // temporary file through a rename.
-pub fn replace_path<'search, 'p>(
-    patterns: &'search Vec<&'search str>,
-    replacements: &'search Vec<&'search str>,
-    path: &'p Path,
-) -> Result<&'p Path> {
-    if path.is_dir() {
-        for entry in fs::read_dir(&path)? {
-            let entry_path = entry?.path();
-            replace_path(patterns, replacements, entry_path.as_path())?;
+fn replace_file<M>(
+    matcher: &M,
+    path: &Path,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<FileReport>
+where
+    M: Search + ?Sized,
+{
+    rewrite_file(path, options.progress.as_deref(), |input, output| {
+        replace_stream_budgeted(matcher, input, output, options, budget)
+    })
+}
+
+fn edit_file(
+    rectangle: &Rectangle,
+    path: &Path,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<FileReport> {
+    rewrite_file(path, options.progress.as_deref(), |input, output| {
+        edit_stream_budgeted(rectangle, input, output, options, budget)
+    })
+}
+
+fn replace_file_interactive<In, Out>(
+    spec: &SearchSpec,
+    path: &Path,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+    prompt: &Mutex<&mut Prompt<In, Out>>,
+) -> Result<FileReport>
+where
+    In: BufRead,
+    Out: Write,
+{
+    let mut prompt = prompt.lock().unwrap_or_else(|e| e.into_inner());
+    if prompt.has_quit() {
+        return Ok(FileReport {
+            path: path.to_owned(),
+            skipped: Some(SkipReason::Quit),
+            ..FileReport::default()
+        });
+    }
+    let name = path.display().to_string();
+    rewrite_file(path, options.progress.as_deref(), |input, output| {
+        let mut counts = Counts::default();
+        let (selected, original) = search_stream(
+            input,
+            |reader| spec.diffs(&options.search, reader),
+            spec.nb_rows(),
+            options,
+            budget,
+            &mut counts,
+        );
+        let (original, shown) = teereader::tee(original);
+        let separator = options.search.separator.clone();
+        let confirmed = Confirm::new(
+            selected,
+            BufReader::new(shown),
+            separator,
+            name,
+            &mut prompt,
+        );
+        write_replaced(Box::new(confirmed), original, output, options)?;
+        Ok(counts)
+    })
+}
+
+/// Writes the new content of a file to a temporary file, renamed over the file once complete.
+///
+/// The temporary file is removed when anything fails, the file being left as it was. It is also
+/// removed when its content is the same as the one of the file, which is then left untouched and
+/// reported as unchanged.
+fn rewrite_file<F>(path: &Path, progress: Option<&dyn Progress>, rewrite: F) -> Result<FileReport>
+where
+    F: FnOnce(&File, &mut Compare<File, File>) -> Result<Counts>,
+{
+    let start = Instant::now();
+    let (input, _) = open_file(path, progress)?;
+    let original = File::open(path).map_err(|e| Error::from(e).in_file(path, Operation::Open))?;
+    let temp_path = temporary_path(path).map_err(|e| e.in_file(path, Operation::CreateTemp))?;
+    let temp_file = File::create_new(&temp_path)
+        .map_err(|e| Error::from(e).in_file(path, Operation::CreateTemp))?;
+    let mut output = Compare::new(temp_file, original);
+    let written = rewrite(&input, &mut output)
+        .and_then(|counts| Ok((counts, output.finish()?)))
+        .map_err(|e| e.in_file(path, Operation::Rewrite));
+    let bytes_read = bytes_read(&input);
+    let (counts, (bytes_written, changed)) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            // The file is left as it was either way, the temporary file is only clutter
+            let _ = fs::remove_file(&temp_path);
+            return skip_file(path, e, bytes_read, start, progress);
         }
-        Ok(path)
-    } else {
-        let input = File::open(&path)?;
-        let temp_path = temporary_path(&path)?;
-        let temp_file = File::create_new(&temp_path)?;
-        replace_stream(patterns, replacements, input, temp_file)?;
-        match fs::rename(temp_path, &path) {
-            Err(e) => Err(Error::IoError(e)),
-            Ok(()) => Ok(path),
+    };
+    if !changed {
+        let _ = fs::remove_file(&temp_path);
+    } else if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(Error::from(e).in_file(path, Operation::Rename));
+    }
+    let report = FileReport {
+        path: path.to_owned(),
+        counts,
+        hits: Vec::new(),
+        stats: FileStats {
+            bytes_read,
+            bytes_written,
+            duration: start.elapsed(),
+            changed,
+        },
+        skipped: None,
+    };
+    Ok(finish_file(report, progress))
+}
+
+/// Reads a file without modifying it, for a dry run.
+fn inspect_file<F>(path: &Path, progress: Option<&dyn Progress>, inspect: F) -> Result<FileReport>
+where
+    F: FnOnce(&File) -> Result<(Vec<Hit>, Counts)>,
+{
+    let start = Instant::now();
+    let (input, _) = open_file(path, progress)?;
+    let inspected = inspect(&input);
+    let bytes_read = bytes_read(&input);
+    let (hits, counts) = match inspected {
+        Ok(inspected) => inspected,
+        Err(e) => {
+            let e = e.in_file(path, Operation::Read);
+            return skip_file(path, e, bytes_read, start, progress);
         }
+    };
+    let report = FileReport {
+        path: path.to_owned(),
+        counts,
+        hits,
+        stats: FileStats {
+            bytes_read,
+            bytes_written: 0,
+            duration: start.elapsed(),
+            changed: false,
+        },
+        skipped: None,
+    };
+    Ok(finish_file(report, progress))
+}
+
+/// Returns the report of a file given up on because of a timeout or a cancellation, or the error
+/// that made processing it fail.
+fn skip_file(
+    path: &Path,
+    error: Error,
+    bytes_read: u64,
+    start: Instant,
+    progress: Option<&dyn Progress>,
+) -> Result<FileReport> {
+    let skipped = SkipReason::from_error(&error).ok_or(error)?;
+    let report = FileReport {
+        path: path.to_owned(),
+        stats: FileStats {
+            bytes_read,
+            duration: start.elapsed(),
+            ..FileStats::default()
+        },
+        skipped: Some(skipped),
+        ..FileReport::default()
+    };
+    Ok(finish_file(report, progress))
+}
+
+/// Opens a file to be searched, along with its size, and tells the progress it is started.
+fn open_file(path: &Path, progress: Option<&dyn Progress>) -> Result<(File, u64)> {
+    let open = || -> Result<(File, u64)> {
+        let input = File::open(path)?;
+        let size = input.metadata()?.len();
+        Ok((input, size))
+    };
+    let (input, size) = open().map_err(|e| e.in_file(path, Operation::Open))?;
+    if let Some(progress) = progress {
+        progress.file_started(path, size);
     }
+    Ok((input, size))
+}
+
+/// Returns the number of bytes read from a file, which may be less than its size when the search
+/// stopped early.
+fn bytes_read(mut file: &File) -> u64 {
+    file.stream_position().unwrap_or_default()
+}
+
+fn finish_file(report: FileReport, progress: Option<&dyn Progress>) -> FileReport {
+    if let Some(progress) = progress {
+        progress.file_finished(&report);
+    }
+    report
+}
+
+// Search and replace the occurrences found by a matcher, like a spec, in a stream.
+//
+// `Selection::max_total` applies to the stream alone. The error policy of the options is not used
+// for a single stream.
+pub fn replace_stream<M, R, W>(
+    matcher: &M,
+    input: R,
+    output: W,
+    options: &Options,
+) -> Result<Counts>
+where
+    M: Search + ?Sized,
+    R: Read,
+    W: Write,
+{
+    let budget = options.selection.budget();
+    replace_stream_budgeted(matcher, input, output, options, budget.as_ref())
+}
+
+// Apply a rectangle edit to every line of a stream.
+//
+// Each edited line counts as an occurrence for the selection, so that the edit can be restricted
+// to some lines with addresses.
+pub fn edit_stream<R, W>(
+    rectangle: &Rectangle,
+    input: R,
+    output: W,
+    options: &Options,
+) -> Result<Counts>
+where
+    R: Read,
+    W: Write,
+{
+    let budget = options.selection.budget();
+    edit_stream_budgeted(rectangle, input, output, options, budget.as_ref())
+}
+
+// Like `replace_stream`, but the replacements are computed by `replace` for each occurrence.
+//
+// `replace` is only called for the occurrences that are kept, in the order of the stream, and
+// once for each row of a block.
+pub fn replace_stream_fn<M, F, R, W>(
+    matcher: &M,
+    replace: &F,
+    input: R,
+    output: W,
+    options: &Options,
+) -> Result<Counts>
+where
+    M: Search + ?Sized,
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]>,
+    R: Read,
+    W: Write,
+{
+    let budget = options.selection.budget();
+    replace_stream_fn_budgeted(matcher, replace, input, output, options, budget.as_ref())
+}
+
+// Search the occurrences found by a matcher in a stream, without replacing them.
+pub fn check_stream<M, R>(matcher: &M, input: R, options: &Options) -> Result<(Vec<Hit>, Counts)>
+where
+    M: Search + ?Sized,
+    R: Read,
+{
+    let budget = options.selection.budget();
+    check_stream_budgeted(matcher, input, options, budget.as_ref())
 }
 
-pub fn replace_stream<'s, R, W>(
-    patterns: &'s Vec<&'s str>,
-    replacements: &'s Vec<&'s str>,
+/// Like `replace_stream`, within the budget of a run.
+///
+/// `budget` is the number of occurrences that can still be kept over all the streams of a run,
+/// see `Selection::budget`; `Selection::max_total` is only enforced through it.
+fn replace_stream_budgeted<M, R, W>(
+    matcher: &M,
     input: R,
+    output: W,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<Counts>
+where
+    M: Search + ?Sized,
+    R: Read,
+    W: Write,
+{
+    let mut counts = Counts::default();
+    let (selected, original) = search_stream(
+        input,
+        |reader| matcher.diffs(&options.search, reader),
+        matcher.nb_rows(),
+        options,
+        budget,
+        &mut counts,
+    );
+    write_replaced(selected, original, output, options)?;
+    Ok(counts)
+}
+
+/// Like `edit_stream`, within the budget of a run, see `replace_stream_budgeted`.
+fn edit_stream_budgeted<R, W>(
+    rectangle: &Rectangle,
+    input: R,
+    output: W,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<Counts>
+where
+    R: Read,
+    W: Write,
+{
+    let mut counts = Counts::default();
+    let (selected, original) = search_stream(
+        input,
+        |reader| {
+            Box::new(RectangleDiffs::new(
+                rectangle,
+                options.search.clone(),
+                reader,
+            ))
+        },
+        1,
+        options,
+        budget,
+        &mut counts,
+    );
+    write_replaced(selected, original, output, options)?;
+    Ok(counts)
+}
+
+/// Like `replace_stream_fn`, within the budget of a run, see `replace_stream_budgeted`.
+fn replace_stream_fn_budgeted<M, F, R, W>(
+    matcher: &M,
+    replace: &F,
+    input: R,
+    output: W,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<Counts>
+where
+    M: Search + ?Sized,
+    F: for<'a> Fn(&'a MatchInfo<'a>) -> Cow<'a, [u8]>,
+    R: Read,
+    W: Write,
+{
+    let mut counts = Counts::default();
+    let (selected, original) = search_stream(
+        input,
+        |reader| matcher.diffs(&options.search, reader),
+        matcher.nb_rows(),
+        options,
+        budget,
+        &mut counts,
+    );
+    let (original, located) = teereader::tee(original);
+    let computed = Computed::new(selected, replace, located, options.search.clone());
+    write_replaced(Box::new(computed), original, output, options)?;
+    Ok(counts)
+}
+
+/// Like `check_stream`, within the budget of a run, see `replace_stream_budgeted`.
+fn check_stream_budgeted<M, R>(
+    matcher: &M,
+    input: R,
+    options: &Options,
+    budget: Option<&AtomicUsize>,
+) -> Result<(Vec<Hit>, Counts)>
+where
+    M: Search + ?Sized,
+    R: Read,
+{
+    let mut counts = Counts::default();
+    // Nothing is written for a dry run, the bytes processed are the ones searched
+    let input = Reported::new(input, options.progress.as_deref());
+    let (selected, original) = search_stream(
+        input,
+        |reader| matcher.diffs(&options.search, reader),
+        matcher.nb_rows(),
+        options,
+        budget,
+        &mut counts,
+    );
+    let separator = options.search.separator.clone();
+    let hits = Checker::new(selected, original, separator).collect::<Result<_>>()?;
+    Ok((hits, counts))
+}
+
+/// Searches a stream with `search` and selects the occurrences to keep, counting them in
+/// `counts`.
+///
+/// Returns the occurrences along with a reader of the original stream, to apply them or to locate
+/// them. The search, and the reads of the original, give up on the stream once the run is
+/// cancelled or timed out.
+fn search_stream<'a, R, S>(
+    input: R,
+    search: S,
+    nb_rows: usize,
+    options: &'a Options,
+    budget: Option<&'a AtomicUsize>,
+    counts: &'a mut Counts,
+) -> (
+    Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a>,
+    impl Read + Seek + 'a,
+)
+where
+    R: Read + 'a,
+    S: FnOnce(Box<dyn Read + 'a>) -> Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a>,
+{
+    let interrupt = Interrupt::new(options);
+    let (searched, original) = teereader::tee(input);
+    let (original, lines) = teereader::tee(original);
+    let diffs = search(Box::new(interrupt.clone().reader(searched)));
+    let diffs = Box::new(interrupt.clone().diffs(diffs));
+    let selected = select(diffs, nb_rows, options, budget, lines, counts);
+    (selected, interrupt.reader(original))
+}
+
+/// Writes the original stream to `output` with the diffs applied.
+fn write_replaced<'a, R, W>(
+    diffs: Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a>,
+    mut original: R,
     mut output: W,
+    options: &Options,
 ) -> Result<()>
 where
     R: Read + Seek,
     W: Write,
 {
-    let (mut input1, mut input2) = teereader::tee(input);
-    let diffs = BufSearcher::new(patterns, replacements, &mut input1);
-    let mut replacer = Replacer::new(Box::new(diffs), &mut input2, &mut output);
-    Ok(loop {
-        match replacer.replace_next_diff() {
-            Err(Error::EndOfIteration) => break,
-            Err(e) => return Err(e),
-            Ok(()) => (),
-        }
-    })
+    let search = options.search.clone();
+    let progress = options.progress.as_deref();
+    Replacer::new(diffs, search, progress, &mut original, &mut output).replace_all()
+}
+
+/// Filters diffs according to a Selection.
+///
+/// `original` is dropped right away when the selection does not need to read the lines, so that
+/// it does not buffer the whole stream.
+fn select<'s, 'i, R>(
+    diffs: Box<dyn Iterator<Item = Result<Diff<'s>>> + 'i>,
+    nb_rows: usize,
+    options: &'i Options,
+    budget: Option<&'i AtomicUsize>,
+    original: R,
+    counts: &'i mut Counts,
+) -> Box<dyn Iterator<Item = Result<Diff<'s>>> + 'i>
+where
+    R: Read + 'i,
+    's: 'i,
+{
+    let selection = &options.selection;
+    let original = selection.reads_lines().then_some(original);
+    let separator = options.search.separator.clone();
+    let selected = Select::new(
+        diffs, nb_rows, selection, budget, original, separator, counts,
+    );
+    // The rows of a block where nothing is replaced were only needed to select the occurrences
+    Box::new(selected.filter(|diff| !matches!(diff, Ok(d) if d.is_noop())))
 }
 
-pub fn replace_single<'s, 'p>(
-    pattern: &'s str,
-    replacement: &'s str,
-    path: &'p Path,
-) -> Result<&'p Path> {
-    let patterns = vec![pattern];
-    let replacements = vec![replacement];
-    let result = replace_path(&patterns, &replacements, path);
-    return result;
+pub fn replace_single(
+    pattern: impl Into<Vec<u8>>,
+    replacement: impl Into<Vec<u8>>,
+    path: &Path,
+) -> Result<&Path> {
+    let spec = SearchSpec::literal(pattern, replacement)?;
+    let options = Options {
+        policy: ErrorPolicy::FailFast,
+        ..Options::default()
+    };
+    replace_path(&spec, path, &options)?;
+    Ok(path)
 }
 
 fn temporary_path(original_path: &Path) -> Result<PathBuf> {
@@ -132,12 +778,23 @@ where
     W: Write,
     'search: 'iterator,
 {
-    diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
-    original: &'search mut R,
-    output: &'search mut W,
+    diffs: Peekable<Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>>,
+    config: SearchConfig,
+    progress: Option<&'iterator dyn Progress>,
+    original: &'iterator mut R,
+    output: &'iterator mut W,
     pos: usize,
+    /// The position in the original up to which the bytes processed were told to the progress
+    reported: usize,
     buffer: Vec<u8>,
     max_buffer_size: usize,
+    /// The columns reached in the original and in the output, only counted when they are used,
+    /// see `SearchConfig::tracks_columns`
+    original_column: usize,
+    output_column: usize,
+    /// The bytes that were read from the original after `pos`, while looking for tabs or for the
+    /// end of the line, all of them before the next diff
+    pending: VecDeque<u8>,
 }
 
 impl<'search, 'iterator, R, W> Replacer<'search, 'iterator, R, W>
@@ -148,49 +805,238 @@ where
 {
     fn new(
         diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
-        original: &'search mut R,
-        output: &'search mut W,
+        config: SearchConfig,
+        progress: Option<&'iterator dyn Progress>,
+        original: &'iterator mut R,
+        output: &'iterator mut W,
     ) -> Self {
         Self {
-            diffs,
+            diffs: diffs.peekable(),
+            config,
+            progress,
             original,
             output,
             pos: 0,
+            reported: 0,
             buffer: Vec::new(),
             max_buffer_size: 16 * 1024 * 1024,
+            original_column: 0,
+            output_column: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Writes the whole output, an I/O error being given the offset reached in the original.
+    fn replace_all(&mut self) -> Result<()> {
+        loop {
+            match self.replace_next_diff() {
+                Ok(true) => (),
+                Ok(false) => break Ok(()),
+                Err(e @ Error::IoError(_)) => {
+                    break Err(Error::Stream {
+                        offset: self.pos,
+                        source: Box::new(e),
+                    })
+                }
+                Err(e) => break Err(e),
+            }
         }
     }
 
-    fn replace_next_diff(self: &mut Self) -> Result<()> {
+    /// Writes the output up to the end of the next diff, returns false once there is none left
+    /// and the rest of the original was copied.
+    fn replace_next_diff(self: &mut Self) -> Result<bool> {
         match self.diffs.next() {
             None => {
                 self.copy_remaining()?;
-                Err(Error::EndOfIteration)
+                Ok(false)
             }
             Some(Err(e)) => return Err(e),
+            Some(Ok(diff)) if diff.pos < self.pos => Err(Error::UnorderedMatch {
+                pos: diff.pos,
+                end: self.pos,
+            }),
             Some(Ok(diff)) => {
                 self.copy_from_original(diff.pos - self.pos)?;
+                if let Some(progress) = self.progress {
+                    progress.match_found(&diff);
+                }
                 self.produce_replacement(diff)?;
-                Ok(())
+                self.report_bytes(self.pos);
+                Ok(true)
             }
         }
     }
 
     fn copy_remaining(self: &mut Self) -> Result<()> {
-        io::copy(self.original, self.output)?;
-        Ok(())
+        let pending: Vec<u8> = self.pending.drain(..).collect();
+        self.output.write_all(&pending)?;
+        self.pos += pending.len();
+        loop {
+            let mut chunk = (&mut *self.original).take(self.max_buffer_size as u64);
+            let copied = io::copy(&mut chunk, self.output)?;
+            if copied == 0 {
+                break Ok(());
+            }
+            self.pos += copied as usize;
+            self.report_bytes(self.pos);
+        }
+    }
+
+    /// Tells the progress about the bytes of the original processed up to `pos`.
+    fn report_bytes(&mut self, pos: usize) {
+        if pos == self.reported {
+            return;
+        }
+        if let Some(progress) = self.progress {
+            progress.bytes_processed((pos - self.reported) as u64);
+        }
+        self.reported = pos;
     }
 
     fn produce_replacement(self: &mut Self, diff: Diff) -> Result<()> {
-        // skip over the length of the pattern in the input
-        self.original.seek_relative(diff.remove as i64)?;
-        self.output.write_all(diff.add.as_bytes())?;
+        if !self.config.tracks_columns() {
+            // skip over the length of the pattern in the input
+            self.original.seek_relative(diff.remove as i64)?;
+            self.output.write_all(&diff.add)?;
+            self.pos += diff.remove;
+            return Ok(());
+        }
+        let mut removed = Vec::with_capacity(diff.remove);
+        removed.extend(self.pending.drain(..));
+        let already_read = removed.len();
+        removed.resize(diff.remove, 0);
+        self.original.read_exact(&mut removed[already_read..])?;
+        self.output.write_all(&diff.add)?;
         self.pos += diff.remove;
+        self.original_column = self.config.advance(self.original_column, &removed);
+        self.output_column = self.config.advance(self.output_column, &diff.add);
+        if self.config.keep_columns {
+            self.keep_columns()?;
+        }
+        if self.original_column != self.output_column {
+            self.align_tabs()?;
+        }
+        Ok(())
+    }
+
+    /// Pads a replacement narrower than the text it replaced with spaces, or removes the spaces
+    /// following a wider one, so that the text after it stays at the same column.
+    ///
+    /// The spaces of the next diff are not removed, nor the ones of another line. Nothing is
+    /// padded at the end of a line.
+    fn keep_columns(&mut self) -> Result<()> {
+        if self.output_column < self.original_column && !self.at_line_end()? {
+            let padding = vec![b' '; self.original_column - self.output_column];
+            self.output.write_all(&padding)?;
+            self.output_column = self.original_column;
+            return Ok(());
+        }
+        let limit = self.next_diff_pos();
+        while self.output_column > self.original_column && self.pos < limit {
+            match self.next_byte()? {
+                Some(b' ') => {
+                    self.pos += 1;
+                    self.original_column += 1;
+                }
+                byte => {
+                    self.pending.extend(byte);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the position of the next diff, if there is one.
+    fn next_diff_pos(&mut self) -> usize {
+        match self.diffs.peek() {
+            Some(Ok(diff)) => diff.pos,
+            _ => usize::MAX,
+        }
+    }
+
+    /// Reads the byte of the original at `pos`, which may already have been read.
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        match self.original.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Returns whether the original is at the end of a line, or of the stream, at `pos`.
+    ///
+    /// The bytes read to tell are kept pending, and none of the next diff is read.
+    fn at_line_end(&mut self) -> Result<bool> {
+        let wanted = self
+            .config
+            .separator
+            .len()
+            .min(self.next_diff_pos().saturating_sub(self.pos));
+        let mut byte = [0];
+        while self.pending.len() < wanted && self.original.read(&mut byte)? > 0 {
+            self.pending.push_back(byte[0]);
+        }
+        let next = self.pending.make_contiguous();
+        Ok((next.is_empty() && wanted > 0) || self.config.separator.starts(next))
+    }
+
+    /// Replaces the tabs following a replacement that changed the width of the line, so that the
+    /// text after them stays at the same column.
+    ///
+    /// The tabs are left as they are if the replacement went past the column of that text.
+    fn align_tabs(&mut self) -> Result<()> {
+        if self.config.tabstop.is_none() {
+            return Ok(());
+        }
+        let limit = self.next_diff_pos();
+        let mut nb_tabs = 0;
+        while self.pos + nb_tabs < limit {
+            match self.next_byte()? {
+                Some(b'\t') => nb_tabs += 1,
+                byte => {
+                    self.pending.extend(byte);
+                    break;
+                }
+            }
+        }
+        if nb_tabs == 0 {
+            return Ok(());
+        }
+        let tabs = vec![b'\t'; nb_tabs];
+        let target = self.config.advance(self.original_column, &tabs);
+        let mut column = self.output_column;
+        let mut nb_aligned = 0;
+        while column < target {
+            column = self.config.advance(column, b"\t");
+            nb_aligned += 1;
+        }
+        let aligned = if nb_aligned > 0 && column == target {
+            vec![b'\t'; nb_aligned]
+        } else {
+            tabs
+        };
+        self.output.write_all(&aligned)?;
+        self.pos += nb_tabs;
+        self.original_column = target;
+        self.output_column = self.config.advance(self.output_column, &aligned);
         Ok(())
     }
 
     fn copy_from_original(self: &mut Self, nb_bytes: usize) -> Result<()> {
+        let end = self.pos + nb_bytes;
         let mut remaining = nb_bytes;
+        let nb_pending = remaining.min(self.pending.len());
+        if nb_pending > 0 {
+            let pending: Vec<u8> = self.pending.drain(..nb_pending).collect();
+            self.output.write_all(&pending)?;
+            self.track_columns(&pending);
+            remaining -= nb_pending;
+        }
         while remaining > 0 {
             let chunk_size = std::cmp::min(remaining, self.max_buffer_size);
             if self.buffer.len() < chunk_size {
@@ -199,13 +1045,27 @@ where
             let slice = &mut self.buffer[..chunk_size];
 
             self.original.read_exact(slice)?;
-            self.output.write_all(&self.buffer)?;
+            self.output.write_all(&self.buffer[..chunk_size])?;
+            if self.config.tracks_columns() {
+                let bytes = &self.buffer[..chunk_size];
+                self.original_column = self.config.advance(self.original_column, bytes);
+                self.output_column = self.config.advance(self.output_column, bytes);
+            }
             remaining -= chunk_size;
+            self.report_bytes(end - remaining);
         }
 
         self.pos += nb_bytes;
         Ok(())
     }
+
+    /// Advances the columns over a byte copied as it is from the original.
+    fn track_columns(&mut self, bytes: &[u8]) {
+        if self.config.tracks_columns() {
+            self.original_column = self.config.advance(self.original_column, bytes);
+            self.output_column = self.config.advance(self.output_column, bytes);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -213,10 +1073,21 @@ mod tests {
     extern crate test;
     use super::*;
     use io::Cursor;
+    use matcher::tests::WordMatcher;
     use std::fs;
     use std::iter;
+    use std::num::NonZeroUsize;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
     use test::Bencher;
 
+    fn fail_fast() -> Options {
+        Options {
+            policy: ErrorPolicy::FailFast,
+            ..Options::default()
+        }
+    }
+
     #[test]
     fn test_replace_file_does_not_exist() {
         let dir = temp_dir();
@@ -224,7 +1095,11 @@ mod tests {
         let result = replace_single("abba", "toto", &path);
         assert!(result.is_err());
         match result.unwrap_err() {
-            Error::IoError(_) => {}
+            Error::File {
+                operation: Operation::Open,
+                source,
+                ..
+            } if matches!(*source, Error::IoError(_)) => {}
             _ => {
                 assert!(false)
             }
@@ -275,13 +1150,39 @@ mod tests {
         let pattern: String = iter::repeat("X").take(bufsearcher::SEARCH_MAX).collect();
         let orig_content = String::new() + &pattern + " and " + &pattern;
         write_file(&path, &orig_content);
-        let result = replace_single(&pattern, "toto", &path);
+        let result = replace_single(pattern, "toto", &path);
         assert!(result.is_ok());
 
         let content = file_content(path);
         assert_eq!(content, "toto and toto")
     }
 
+    #[test]
+    fn test_replace_pattern_larger_than_window() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba");
+        let mut options = fail_fast();
+        options.search.window = NonZeroUsize::new(3);
+        let result = replace_path(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            &path,
+            &options,
+        );
+        let e = result.unwrap_err();
+        assert!(matches!(e.root_cause(), Error::WindowTooSmall { .. }));
+        assert_eq!(e.path(), Some(path.as_path()));
+        assert_eq!(file_content(&path), "abba abba");
+        options.search.window = NonZeroUsize::new(4);
+        let result = replace_path(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            &path,
+            &options,
+        );
+        assert!(result.is_ok());
+        assert_eq!(file_content(path), "toto toto");
+    }
+
     #[test]
     fn test_replacer_basic() {
         let mut original = Cursor::new("abba");
@@ -289,11 +1190,19 @@ mod tests {
         let diff = Diff {
             pos: 0,
             remove: 4,
-            add: "toto",
+            add: "toto".as_bytes().into(),
+            anchor: 0,
+            row: 0,
         };
         let diffs = iter::once(Ok(diff));
         {
-            let mut replacer = Replacer::new(Box::new(diffs), &mut original, &mut output);
+            let mut replacer = Replacer::new(
+                Box::new(diffs),
+                SearchConfig::default(),
+                None,
+                &mut original,
+                &mut output,
+            );
             let result = replacer.replace_next_diff();
             assert!(result.is_ok());
         }
@@ -303,6 +1212,284 @@ mod tests {
         assert_eq!(content, "toto");
     }
 
+    #[test]
+    fn test_replacer_copies_only_the_chunk() {
+        // The buffer keeps the size of the longest copy, a shorter copy must only write its bytes
+        let mut original = Cursor::new("0123456789abba-abba");
+        let mut output = Cursor::new(Vec::new());
+        let diff = |pos| {
+            Ok(Diff {
+                pos,
+                remove: 4,
+                add: b"toto"[..].into(),
+                anchor: pos,
+                row: 0,
+            })
+        };
+        let diffs = vec![diff(10), diff(15)];
+        let mut replacer = Replacer::new(
+            Box::new(diffs.into_iter()),
+            SearchConfig::default(),
+            None,
+            &mut original,
+            &mut output,
+        );
+        assert!(replacer.replace_all().is_ok());
+        drop(replacer);
+        assert_eq!(output.into_inner(), b"0123456789toto-toto");
+    }
+
+    #[test]
+    fn test_replacer_rejects_unordered_diffs() {
+        let diff = |pos, remove| {
+            Ok(Diff {
+                pos,
+                remove,
+                add: b"toto"[..].into(),
+                anchor: pos,
+                row: 0,
+            })
+        };
+        // a diff before the previous one, and a diff overlapping the previous one
+        for (diffs, pos, end) in [
+            (vec![diff(10, 4), diff(2, 4)], 2, 14),
+            (vec![diff(2, 4), diff(4, 4)], 4, 6),
+        ] {
+            let mut original = Cursor::new("0123456789abba-abba");
+            let mut output = Vec::new();
+            let mut replacer = Replacer::new(
+                Box::new(diffs.into_iter()),
+                SearchConfig::default(),
+                None,
+                &mut original,
+                &mut output,
+            );
+            let result = replacer.replace_all();
+            assert!(
+                matches!(result, Err(Error::UnorderedMatch { pos: p, end: e }) if p == pos && e == end),
+                "{result:?}"
+            );
+        }
+    }
+
+    fn replace_tabs(content: &str, pattern: &str, replacement: &str) -> String {
+        let search = SearchConfig {
+            tabstop: NonZeroUsize::new(8),
+            ..SearchConfig::default()
+        };
+        replace_with(content, pattern, replacement, search)
+    }
+
+    fn replace_with(
+        content: &str,
+        pattern: &str,
+        replacement: &str,
+        search: SearchConfig,
+    ) -> String {
+        let spec = SearchSpec::literal(pattern, replacement).unwrap();
+        let options = Options {
+            search,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let input = Cursor::new(content);
+        let result = replace_stream(&spec, input, &mut output, &options);
+        assert!(result.is_ok());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_replace_keeps_tab_alignment() {
+        assert_eq!(
+            replace_tabs("k\t\tvalue", "k", "kkkkkkkkkk"),
+            "kkkkkkkkkk\tvalue"
+        );
+        assert_eq!(
+            replace_tabs("kkkkkkkkkk\tvalue", "kkkkkkkkkk", "k"),
+            "k\t\tvalue"
+        );
+        assert_eq!(replace_tabs("kkk\tvalue", "kkk", "kk"), "kk\tvalue");
+        // the replacement goes past the tab stop, the tab is kept
+        assert_eq!(
+            replace_tabs("key\tvalue", "key", "longer_key"),
+            "longer_key\tvalue"
+        );
+        assert_eq!(
+            replace_tabs("x\t1\nx\t\t2\nxx", "x", "yyyyyyyyy"),
+            "yyyyyyyyy\t1\nyyyyyyyyy\t2\nyyyyyyyyyyyyyyyyyy"
+        );
+    }
+
+    #[test]
+    fn test_replace_keep_columns() {
+        let search = SearchConfig {
+            keep_columns: true,
+            ..SearchConfig::default()
+        };
+        let replace = |content, pattern, replacement| {
+            replace_with(content, pattern, replacement, search.clone())
+        };
+        assert_eq!(replace("ab  x", "ab", "a"), "a   x");
+        // nothing follows on the line
+        assert_eq!(replace("abc\nx", "abc", "a"), "a\nx");
+        assert_eq!(replace("x abc", "abc", "a"), "x a");
+        assert_eq!(replace("abc  x", "abc", "abcde"), "abcdex");
+        // there are not enough spaces after the replacement
+        assert_eq!(replace("abc x", "abc", "abcde"), "abcdex");
+        assert_eq!(replace("ab\nab", "ab", "abc"), "abc\nabc");
+        // the next occurrence is not removed
+        assert_eq!(replace("ab ab", "ab", "abcd"), "abcdabcd");
+        let search = SearchConfig {
+            columns: ColumnMode::Chars,
+            tabstop: NonZeroUsize::new(4),
+            keep_columns: true,
+            ..SearchConfig::default()
+        };
+        assert_eq!(replace_with("é  x", "é", "ab", search.clone()), "ab x");
+        assert_eq!(replace_with("ab \tx", "ab", "abcd", search), "abcd\tx");
+        let search = SearchConfig {
+            separator: "crlf".parse().unwrap(),
+            keep_columns: true,
+            ..SearchConfig::default()
+        };
+        let replace = |content, pattern, replacement| {
+            replace_with(content, pattern, replacement, search.clone())
+        };
+        assert_eq!(replace("abc\r\nx", "abc", "a"), "a\r\nx");
+        assert_eq!(replace("abc\rx", "abc", "a"), "a  \rx");
+    }
+
+    #[test]
+    fn test_replace_bytes() {
+        let content = b"\x7fELF\x00\xff\xfe\x00abba\x00";
+        let mut output = Vec::new();
+        let input = Cursor::new(content);
+        let options = Options::default();
+        let spec = SearchSpec::literal(&b"\x00\xff"[..], "\n").unwrap();
+        let result = replace_stream(&spec, input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"\x7fELF\n\xfe\x00abba\x00");
+        // the rows of a block are separated by NUL bytes
+        let search = SearchConfig {
+            separator: "nul".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        let options = Options {
+            search,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let input = Cursor::new(content);
+        let spec = SearchSpec::new([(&b"\xff\xfe"[..], &b"\xef\xbb"[..]), (b"abba", b"")]).unwrap();
+        let result = replace_stream(&spec, input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"\x7fELF\x00\xef\xbb\x00\x00");
+    }
+
+    #[test]
+    fn test_replace_with_matcher() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abbas\nabba");
+        let matcher = WordMatcher {
+            word: "abba",
+            replacement: "toto",
+        };
+        let result = replace_path(&matcher, dir.path(), &fail_fast());
+        assert!(result.is_ok());
+        assert_eq!(file_content(&path), "toto abbas\ntoto");
+        // the occurrences of a matcher are selected like the other ones
+        let mut options = fail_fast();
+        options.selection.nth = Some(2);
+        let file_glob = dir.path().join("*");
+        write_file(&path, "abba abba abba");
+        let result = replace_glob(&matcher, file_glob.to_str().unwrap(), &options);
+        assert!(result.is_ok());
+        assert_eq!(file_content(&path), "abba toto abba");
+        // a matcher can be given as a trait object
+        let matcher: &dyn Matcher = &matcher;
+        let mut output = Vec::new();
+        let result = replace_stream(
+            matcher,
+            Cursor::new("abba abbas abba"),
+            &mut output,
+            &options,
+        );
+        assert!(result.is_ok());
+        assert_eq!(output, b"abba abbas toto");
+    }
+
+    #[test]
+    fn test_matcher_timeout() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba");
+        let matcher = WordMatcher {
+            word: "abba",
+            replacement: "toto",
+        };
+        let options = Options {
+            timeout: Some(Duration::ZERO),
+            ..fail_fast()
+        };
+        let report = replace_path(&matcher, &path, &options).unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+        assert_eq!(file_content(&path), "abba abba");
+        let report = check_path(&matcher, &path, &options).unwrap();
+        assert_eq!(report.files[0].skipped, Some(SkipReason::Timeout));
+    }
+
+    #[test]
+    fn test_replace_with_callback() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "id id\nid");
+        let counter = AtomicUsize::new(0);
+        let result = replace_path_fn(
+            &SearchSpec::search(["id"]).unwrap(),
+            &|_| {
+                let n = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Cow::Owned(format!("id{n}").into_bytes())
+            },
+            &path,
+            &fail_fast(),
+        );
+        assert!(result.is_ok());
+        assert_eq!(file_content(&path), "id0 id1\nid2");
+
+        let result = replace_path_fn(
+            &SearchSpec::search(["id0", "id2"]).unwrap(),
+            &|info| match info.row {
+                0 => Cow::Owned(format!("{}:{}", info.line, info.column).into_bytes()),
+                _ => Cow::Borrowed(&info.bytes[..2]),
+            },
+            &path,
+            &fail_fast(),
+        );
+        assert!(result.is_ok());
+        assert_eq!(file_content(&path), "1:1 id1\nid");
+
+        // a matcher finds its own occurrences, whose bytes are given to the callback
+        write_file(&path, "ab abba abbba");
+        let matcher = WordMatcher {
+            word: "abba",
+            replacement: "",
+        };
+        let result = replace_path_fn(
+            &matcher,
+            &|info| {
+                let bytes = info.bytes.to_ascii_uppercase();
+                Cow::Owned(
+                    format!("{}{}", info.column, String::from_utf8_lossy(&bytes)).into_bytes(),
+                )
+            },
+            &path,
+            &fail_fast(),
+        );
+        assert!(result.is_ok());
+        assert_eq!(file_content(&path), "ab 4ABBA abbba");
+    }
+
     #[test]
     fn test_replace_in_dir() {
         let dir = temp_dir();
@@ -315,6 +1502,156 @@ mod tests {
         assert_eq!(content, "toto")
     }
 
+    #[test]
+    fn test_replace_in_nested_dirs() {
+        let dir = temp_dir();
+        let child_dir = dir.path().join("child");
+        assert!(fs::create_dir(&child_dir).is_ok());
+        let file1 = dir.path().join("file1");
+        write_file(&file1, "abba");
+        let file2 = child_dir.join("file2");
+        write_file(&file2, "abba");
+        let result = replace_path(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            dir.path(),
+            &Options::default(),
+        );
+        assert!(result.is_ok());
+        let report = result.unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert!(report.errors.is_empty());
+        assert!(report.files.iter().all(|f| f.stats.changed));
+
+        assert_eq!(file_content(file1), "toto");
+        assert_eq!(file_content(file2), "toto");
+    }
+
+    #[test]
+    fn test_replace_stats() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba abba");
+        let spec = SearchSpec::literal("abba", "abba").unwrap();
+        let report = replace_path(&spec, &path, &fail_fast()).unwrap();
+        let stats = report.files[0].stats;
+        assert_eq!((stats.bytes_read, stats.bytes_written), (9, 9));
+        assert!(!stats.changed);
+        assert_eq!(report.nb_kept(), 2);
+        // the temporary file is removed, the file left untouched
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let spec = SearchSpec::literal("abba", "toto!").unwrap();
+        let report = replace_path(&spec, &path, &fail_fast()).unwrap();
+        let stats = report.files[0].stats;
+        assert_eq!((stats.bytes_read, stats.bytes_written), (9, 11));
+        assert!(stats.changed);
+        assert_eq!(file_content(&path), "toto! toto!");
+    }
+
+    #[test]
+    fn test_replace_in_dir_continue_on_error() {
+        let dir = temp_dir();
+        let file = dir.path().join("file");
+        write_file(&file, "abba");
+        let dangling = dir.path().join("dangling");
+        assert!(std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).is_ok());
+        let result = replace_path(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            dir.path(),
+            &Options::default(),
+        );
+        assert!(result.is_ok());
+        let report = result.unwrap();
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.errors.len(), 1);
+
+        assert_eq!(file_content(file), "toto");
+    }
+
+    #[test]
+    fn test_replace_in_dir_fail_fast() {
+        let dir = temp_dir();
+        let dangling = dir.path().join("dangling");
+        assert!(std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).is_ok());
+        let result = replace_path(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            dir.path(),
+            &fail_fast(),
+        );
+        let e = result.unwrap_err();
+        assert_eq!(e.path(), Some(dangling.as_path()));
+        assert!(matches!(e.root_cause(), Error::IoError(_)));
+    }
+
+    #[test]
+    fn test_check_does_not_modify() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba\n_abba");
+        let result = check_path(
+            &SearchSpec::literal("abba", "").unwrap(),
+            &path,
+            &fail_fast(),
+        );
+        assert!(result.is_ok());
+        let report = result.unwrap();
+        assert_eq!(report.files.len(), 1);
+        let file = &report.files[0];
+        assert_eq!(file.path, path);
+        let locations: Vec<_> = file.hits.iter().map(|h| (h.line, h.column)).collect();
+        assert_eq!(locations, vec![(1, 1), (2, 2)]);
+        assert_eq!(file.stats.bytes_read, 10);
+        assert_eq!(file.stats.bytes_written, 0);
+        assert!(!file.stats.changed);
+
+        let content = file_content(path);
+        assert_eq!(content, "abba\n_abba")
+    }
+
+    #[test]
+    fn test_check_glob_clean() {
+        let dir = temp_dir();
+        write_file(dir.path().join("file1"), "hello file1!");
+        write_file(dir.path().join("file2"), "hello file2!");
+        let file_glob = dir.path().as_os_str().to_str().unwrap().to_owned() + "/*";
+        let result = check_glob(
+            &SearchSpec::literal("goodbye", "").unwrap(),
+            &file_glob,
+            &fail_fast(),
+        );
+        assert!(result.is_ok());
+        let report = result.unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert!(report.files.iter().all(|f| f.hits.is_empty()));
+    }
+
+    #[test]
+    fn test_replace_interactive() {
+        let dir = temp_dir();
+        let file1 = dir.path().join("file1");
+        write_file(&file1, "abba abba abba");
+        let file2 = dir.path().join("file2");
+        write_file(&file2, "abba");
+        let file3 = dir.path().join("file3");
+        write_file(&file3, "abba");
+        let mut prompt = Prompt::new(Cursor::new("y\nn\ny\nq\n"), Vec::new());
+
+        let file_glob = dir.path().as_os_str().to_str().unwrap().to_owned() + "/*";
+        let result = replace_glob_interactive(
+            &SearchSpec::literal("abba", "toto").unwrap(),
+            &file_glob,
+            &fail_fast(),
+            &mut prompt,
+        );
+        assert!(prompt.has_quit());
+        let skipped: Vec<_> = result.unwrap().files.iter().map(|f| f.skipped).collect();
+        assert_eq!(skipped, vec![None, None, Some(SkipReason::Quit)]);
+
+        assert_eq!(file_content(file1), "toto abba toto");
+        assert_eq!(file_content(file2), "abba");
+        assert_eq!(file_content(file3), "abba");
+    }
+
     #[test]
     fn test_replace_glob() {
         let dir = temp_dir();
@@ -331,7 +1668,11 @@ mod tests {
         let paths: Vec<_> = glob::glob(&file_glob).unwrap().collect();
         print!("{paths:?}");
 
-        let result = replace_glob(&vec!["hello"], &vec!["goodbye"], &file_glob);
+        let result = replace_glob(
+            &SearchSpec::literal("hello", "goodbye").unwrap(),
+            &file_glob,
+            &Options::default(),
+        );
         assert!(result.is_ok());
 
         let result1 = file_content(file1);
@@ -364,10 +1705,9 @@ mod tests {
     }
 
     fn time_ved(file_path: &Path) -> Duration {
-        let patterns = vec!["X"];
-        let replacements = vec!["Y"];
+        let spec = SearchSpec::literal("X", "Y").unwrap();
         let start = Instant::now();
-        assert!(replace_path(&patterns, &replacements, file_path).is_ok());
+        assert!(replace_path(&spec, file_path, &fail_fast()).is_ok());
         start.elapsed()
     }
 
@@ -402,42 +1742,39 @@ mod tests {
     #[bench]
     fn bench_replacer_all_hits(b: &mut Bencher) {
         let input_str: String = iter::repeat("X").take(10000).collect();
-        let patterns = vec!["X"];
-        let replacements = vec!["Y"];
+        let spec = SearchSpec::literal("X", "Y").unwrap();
         b.iter(move || {
             let input = Cursor::new(&input_str);
             let output = Cursor::new(Vec::new());
-            replace_stream(&patterns, &replacements, input, output)
+            replace_stream(&spec, input, output, &Options::default())
         });
     }
 
     #[bench]
     fn bench_replacer_no_hits(b: &mut Bencher) {
         let input_str: String = iter::repeat("X").take(10000).collect();
-        let patterns = vec!["Y"];
-        let replacements = vec!["W"];
+        let spec = SearchSpec::literal("Y", "W").unwrap();
         b.iter(move || {
             let input = Cursor::new(&input_str);
             let output = Cursor::new(Vec::new());
-            replace_stream(&patterns, &replacements, input, output)
+            replace_stream(&spec, input, output, &Options::default())
         });
     }
 
     #[bench]
     fn bench_replacer_1_in_2_hits_large(b: &mut Bencher) {
         let input_str: String = iter::repeat("X_").take(10000000).collect();
-        let patterns = vec!["X"];
-        let replacements = vec!["W"];
+        let spec = SearchSpec::literal("X", "W").unwrap();
         b.iter(move || {
             let input = Cursor::new(&input_str);
             let output = Cursor::new(Vec::new());
-            replace_stream(&patterns, &replacements, input, output)
+            replace_stream(&spec, input, output, &Options::default())
         });
     }
 
     fn parallel_bench(b: &mut Bencher, nb_files: usize) {
-        let patterns_x = vec!["X"];
-        let patterns_y = vec!["Y"];
+        let x_to_y = SearchSpec::literal("X", "Y").unwrap();
+        let y_to_x = SearchSpec::literal("Y", "X").unwrap();
         let dir = temp_dir();
         let content: String = iter::repeat("XH").take(1000).collect();
         for i in 0..nb_files {
@@ -448,8 +1785,8 @@ mod tests {
         let file_glob = dir.path().as_os_str().to_str().unwrap().to_owned() + "/**/*";
 
         b.iter(
-            move || match replace_glob(&patterns_x, &patterns_y, &file_glob) {
-                Ok(_) => replace_glob(&patterns_y, &patterns_x, &file_glob),
+            move || match replace_glob(&x_to_y, &file_glob, &fail_fast()) {
+                Ok(_) => replace_glob(&y_to_x, &file_glob, &fail_fast()),
                 Err(e) => Err(e),
             },
         );
@@ -0,0 +1,23 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::cancel::CancelToken;
+use crate::replacer::pool::ErrorPolicy;
+use crate::replacer::progress::Observer;
+use crate::replacer::select::Selection;
+use std::time::Duration;
+
+/// The settings of a run, shared by every file it processes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Options {
+    /// What to do with the other files once one of them failed
+    pub policy: ErrorPolicy,
+    /// Which occurrences are kept
+    pub selection: Selection,
+    /// How the patterns are matched
+    pub search: SearchConfig,
+    /// What is told about the progress of the run, if anything
+    pub progress: Option<Observer>,
+    /// Stops the run once cancelled, checked between the files and while searching them
+    pub cancel: Option<CancelToken>,
+    /// The longest time spent searching a file, the files taking longer are skipped
+    pub timeout: Option<Duration>,
+}
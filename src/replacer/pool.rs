@@ -0,0 +1,231 @@
+use crate::replacer::cancel::CancelToken;
+use crate::replacer::error::{Error, Operation, Result};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The result of processing each file, along with the value produced for that file.
+pub type FileResults<T> = Vec<Result<(PathBuf, T)>>;
+
+type IndexedResults<T> = Vec<(usize, Result<(PathBuf, T)>)>;
+
+/// What to do with the remaining paths once processing one of them failed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// Keep processing the other paths and report every error.
+    #[default]
+    Continue,
+    /// Stop handing out new paths after the first error and return that error.
+    FailFast,
+}
+
+/// A fixed number of scoped threads that process paths from a shared iterator.
+///
+/// The iterator is consumed lazily, so a directory walk can keep producing paths while the first
+/// files are already being processed.
+pub struct WorkerPool {
+    nb_workers: usize,
+    policy: ErrorPolicy,
+    /// No path is handed out once it is cancelled
+    cancel: Option<CancelToken>,
+}
+
+impl WorkerPool {
+    pub fn new(policy: ErrorPolicy) -> Self {
+        let nb_workers = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_workers(nb_workers, policy)
+    }
+
+    pub fn with_workers(nb_workers: usize, policy: ErrorPolicy) -> Self {
+        Self {
+            nb_workers: nb_workers.max(1),
+            policy,
+            cancel: None,
+        }
+    }
+
+    /// Stops handing out new paths once `cancel` is cancelled, the paths being processed are
+    /// finished.
+    pub fn cancellable(mut self, cancel: Option<CancelToken>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Calls `work` on every path yielded by `paths`.
+    ///
+    /// The results are returned in the order of the iterator, along with their path.
+    /// Under `ErrorPolicy::FailFast`, the first error (in iterator order) is returned instead.
+    /// A panic of `work` is caught and reported as the error of the path it was processing.
+    pub fn run<I, T, F>(&self, paths: I, work: F) -> Result<FileResults<T>>
+    where
+        I: Iterator<Item = Result<PathBuf>> + Send,
+        T: Send,
+        F: Fn(&Path) -> Result<T> + Sync,
+    {
+        let queue = Mutex::new(paths.enumerate());
+        let results = Mutex::new(Vec::new());
+        let stop = AtomicBool::new(false);
+
+        let panics: Vec<Error> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.nb_workers)
+                .map(|_| scope.spawn(|| self.work_loop(&queue, &results, &stop, &work)))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().err())
+                .map(Error::from)
+                .collect()
+        });
+
+        let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results.sort_by_key(|(index, _)| *index);
+        let mut results: Vec<_> = results.into_iter().map(|(_, result)| result).collect();
+        results.extend(panics.into_iter().map(Err));
+
+        if self.policy == ErrorPolicy::FailFast {
+            if let Some(index) = results.iter().position(|r| r.is_err()) {
+                results.swap_remove(index)?;
+            }
+        }
+        Ok(results)
+    }
+
+    fn work_loop<I, T, F>(
+        &self,
+        queue: &Mutex<I>,
+        results: &Mutex<IndexedResults<T>>,
+        stop: &AtomicBool,
+        work: &F,
+    ) where
+        I: Iterator<Item = (usize, Result<PathBuf>)>,
+        F: Fn(&Path) -> Result<T>,
+    {
+        while !stop.load(Ordering::Relaxed) && !self.is_cancelled() {
+            let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+            let (index, path) = match next {
+                None => break,
+                Some(p) => p,
+            };
+            let result =
+                path.and_then(
+                    |path| match panic::catch_unwind(AssertUnwindSafe(|| work(&path))) {
+                        Ok(result) => result.map(|value| (path, value)),
+                        Err(payload) => {
+                            Err(Error::from(payload).in_file(&path, Operation::Process))
+                        }
+                    },
+                );
+            if result.is_err() && self.policy == ErrorPolicy::FailFast {
+                stop.store(true, Ordering::Relaxed);
+            }
+            results
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((index, result));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn paths(names: &[&str]) -> impl Iterator<Item = Result<PathBuf>> + Send {
+        names
+            .iter()
+            .map(|n| Ok(PathBuf::from(n)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn fail_on_b(path: &Path) -> Result<()> {
+        if path == Path::new("b") {
+            Err(Error::PathError("b".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_results_in_order() {
+        let pool = WorkerPool::with_workers(4, ErrorPolicy::Continue);
+        let names = ["a", "b", "c", "d", "e", "f"];
+        let result = pool.run(paths(&names), |_| Ok(()));
+        let results: Vec<_> = result.unwrap().into_iter().map(|r| r.unwrap().0).collect();
+        let expected: Vec<_> = names.iter().map(PathBuf::from).collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_continue_collects_errors() {
+        let pool = WorkerPool::with_workers(2, ErrorPolicy::Continue);
+        let result = pool.run(paths(&["a", "b", "c"]), fail_on_b);
+        let results = result.unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::PathError(_))));
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn test_fail_fast_stops() {
+        let pool = WorkerPool::with_workers(1, ErrorPolicy::FailFast);
+        let calls = AtomicUsize::new(0);
+        let result = pool.run(paths(&["a", "b", "c"]), |path| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            fail_on_b(path)
+        });
+        assert!(matches!(result, Err(Error::PathError(_))));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_iterator_errors_are_reported() {
+        let pool = WorkerPool::with_workers(2, ErrorPolicy::Continue);
+        let input = vec![
+            Ok(PathBuf::from("a")),
+            Err(Error::PathError("x".to_string())),
+        ];
+        let results = pool.run(input.into_iter(), |_| Ok(())).unwrap();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::PathError(_))));
+    }
+
+    #[test]
+    fn test_panic_is_reported() {
+        let pool = WorkerPool::with_workers(1, ErrorPolicy::Continue);
+        let results = pool
+            .run(paths(&["a", "b"]), |path| {
+                if path == Path::new("a") {
+                    panic!("boom")
+                }
+                Ok(())
+            })
+            .unwrap();
+        let e = results[0].as_ref().unwrap_err();
+        assert_eq!(e.path(), Some(Path::new("a")));
+        assert!(matches!(e.root_cause(), Error::ThreadPanic(s) if s == "boom"));
+        assert!(results[1].is_ok());
+    }
+
+    #[test]
+    fn test_cancelled_pool_stops() {
+        let cancel = CancelToken::new();
+        let pool =
+            WorkerPool::with_workers(1, ErrorPolicy::Continue).cancellable(Some(cancel.clone()));
+        let results = pool
+            .run(paths(&["a", "b", "c"]), |_| {
+                cancel.cancel();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+}
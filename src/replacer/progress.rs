@@ -0,0 +1,99 @@
+use crate::replacer::diff::Diff;
+use crate::replacer::report::FileReport;
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Follows a run as it goes, to show its progress.
+///
+/// The methods are called from the threads processing the files, at the same time for different
+/// files, so they should return quickly. They do nothing by default.
+pub trait Progress: Send + Sync {
+    /// A file of `size` bytes is about to be processed.
+    fn file_started(&self, _path: &Path, _size: u64) {}
+
+    /// `nb_bytes` more bytes of the original were written to the output of the stream being
+    /// replaced by the calling thread, or were searched for a dry run.
+    fn bytes_processed(&self, _nb_bytes: u64) {}
+
+    /// An occurrence kept is being replaced, `diff` being one row of a block.
+    fn match_found(&self, _diff: &Diff) {}
+
+    /// A file was processed, the ones that failed are not reported.
+    fn file_finished(&self, _report: &FileReport) {}
+}
+
+/// A `Progress` shared by the options of a run.
+///
+/// Two observers are equal when they share the same `Progress`.
+#[derive(Clone)]
+pub struct Observer(Arc<dyn Progress>);
+
+impl Observer {
+    pub fn new(progress: Arc<dyn Progress>) -> Self {
+        Self(progress)
+    }
+}
+
+impl Deref for Observer {
+    type Target = dyn Progress;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+impl PartialEq for Observer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Observer {}
+
+/// A reader telling a progress about the bytes read, for the streams that are only searched.
+pub(crate) struct Reported<'a, R> {
+    reader: R,
+    progress: Option<&'a dyn Progress>,
+}
+
+impl<'a, R: Read> Reported<'a, R> {
+    pub(crate) fn new(reader: R, progress: Option<&'a dyn Progress>) -> Self {
+        Self { reader, progress }
+    }
+}
+
+impl<R: Read> Read for Reported<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        if let Some(progress) = self.progress.filter(|_| len > 0) {
+            progress.bytes_processed(len as u64);
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Silent;
+
+    impl Progress for Silent {}
+
+    #[test]
+    fn test_observer_eq() {
+        let progress: Arc<dyn Progress> = Arc::new(Silent);
+        let observer = Observer::new(progress.clone());
+        assert_eq!(observer, Observer::new(progress));
+        assert_ne!(observer, Observer::new(Arc::new(Silent)));
+    }
+}
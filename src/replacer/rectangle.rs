@@ -0,0 +1,295 @@
+use crate::replacer::bufsearcher::SearchConfig;
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// An edit of the same columns on every line: the text of the columns `start..end` is replaced.
+///
+/// The columns start at 0 and are counted like the ones of blocks, see `SearchConfig`. An empty
+/// range inserts the text, an empty text deletes the columns.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Rectangle {
+    pub start: usize,
+    pub end: usize,
+    pub text: Vec<u8>,
+}
+
+/// A range of columns, parsed from `N` or `N-M` where the columns start at 1 and M is included.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ColumnRange {
+    /// The first column of the range, starting at 0
+    pub start: usize,
+    /// The column following the range, starting at 0
+    pub end: usize,
+}
+
+impl FromStr for ColumnRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let parse = |n: &str| match n.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid column: {n:?}")),
+            Ok(n) => Ok(n),
+        };
+        let (first, last) = match s.split_once('-') {
+            None => (parse(s)?, parse(s)?),
+            Some((first, last)) => (parse(first)?, parse(last)?),
+        };
+        if last < first {
+            return Err(format!("invalid column range: {s:?}"));
+        }
+        Ok(Self {
+            start: first - 1,
+            end: last,
+        })
+    }
+}
+
+/// Produces the Diffs applying a Rectangle to every line of a stream.
+///
+/// The stream is read one line at a time. A line that does not reach the first column of the
+/// rectangle is left as it is, and so is a line where that column is in the middle of a wide
+/// character or of an expanded tab. A line that ends before the last column is edited up to its
+/// end, and when the last column is in the middle of a wide character or of an expanded tab, the
+/// edit stops before that character.
+pub struct RectangleDiffs<'search, R>
+where
+    R: Read,
+{
+    rectangle: &'search Rectangle,
+    config: SearchConfig,
+    reader: BufReader<R>,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl<'search, R> RectangleDiffs<'search, R>
+where
+    R: Read,
+{
+    pub fn new(rectangle: &'search Rectangle, config: SearchConfig, reader: R) -> Self {
+        Self {
+            rectangle,
+            config,
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reads the next line, with its separator if it has one.
+    ///
+    /// Returns false if the stream was already over.
+    fn read_line(&mut self) -> Result<bool> {
+        self.line.clear();
+        let separator = &self.config.separator;
+        loop {
+            let nb_read = self
+                .reader
+                .read_until(separator.last_byte(), &mut self.line)?;
+            if nb_read == 0 || separator.ends(&self.line) {
+                return Ok(!self.line.is_empty());
+            }
+        }
+    }
+
+    fn next_diff(&mut self) -> Result<Option<Diff<'search>>> {
+        while self.read_line()? {
+            let line_start = self.pos;
+            self.pos += self.line.len();
+            if let Some(diff) = self.edit_line(line_start) {
+                return Ok(Some(diff));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the diff applying the rectangle to the current line, which starts at `line_start`.
+    fn edit_line(&self, line_start: usize) -> Option<Diff<'search>> {
+        let separator = &self.config.separator;
+        let text = match separator.ends(&self.line) {
+            true => &self.line[..self.line.len() - separator.len()],
+            false => &self.line[..],
+        };
+        let start = match self.column_index(text, self.rectangle.start) {
+            Column::At(start) => start,
+            Column::Inside(_) | Column::Beyond => return None,
+        };
+        let end = match self.column_index(text, self.rectangle.end) {
+            Column::At(end) | Column::Inside(end) => end.max(start),
+            Column::Beyond => text.len(),
+        };
+        let diff = Diff {
+            pos: line_start + start,
+            remove: end - start,
+            add: self.rectangle.text.as_slice().into(),
+            anchor: line_start + start,
+            row: 0,
+        };
+        (!diff.is_noop()).then_some(diff)
+    }
+
+    /// Returns where the given column is in `text`.
+    ///
+    /// The bytes that take no column belong to the character before them.
+    fn column_index(&self, text: &[u8], column: usize) -> Column {
+        let mut index = 0;
+        let mut current = 0;
+        // the start of the character reaching the current column
+        let mut char_start = 0;
+        while current < column && index < text.len() {
+            let next = self.config.next_column(current, &text[index..]);
+            if next != current {
+                char_start = index;
+            }
+            current = next;
+            index += 1;
+        }
+        if current < column {
+            return Column::Beyond;
+        }
+        if current > column {
+            return Column::Inside(char_start);
+        }
+        while index < text.len() && self.config.columns.width(&text[index..]) == 0 {
+            index += 1;
+        }
+        Column::At(index)
+    }
+}
+
+/// Where a column is in a line.
+enum Column {
+    /// At the first byte of a character, or at the end of the line, given as an index
+    At(usize),
+    /// In the middle of a wide character or of an expanded tab, starting at the index
+    Inside(usize),
+    /// After the end of the line
+    Beyond,
+}
+
+impl<'search, R> Iterator for RectangleDiffs<'search, R>
+where
+    R: Read,
+{
+    type Item = Result<Diff<'search>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'search>>> {
+        self.next_diff().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replacer::columns::ColumnMode;
+    use std::io::Cursor;
+    use std::num::NonZeroUsize;
+
+    fn rectangle(columns: &str, text: &str) -> Rectangle {
+        let range: ColumnRange = columns.parse().unwrap();
+        Rectangle {
+            start: range.start,
+            end: range.end,
+            text: text.as_bytes().to_vec(),
+        }
+    }
+
+    fn edits(content: &str, rectangle: &Rectangle, config: SearchConfig) -> Vec<(usize, usize)> {
+        RectangleDiffs::new(rectangle, config, Cursor::new(content))
+            .map(|d| d.unwrap())
+            .map(|d| (d.pos, d.remove))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("3-5".parse(), Ok(ColumnRange { start: 2, end: 5 }));
+        assert_eq!("3".parse(), Ok(ColumnRange { start: 2, end: 3 }));
+        assert!("0-5".parse::<ColumnRange>().is_err());
+        assert!("5-3".parse::<ColumnRange>().is_err());
+        assert!("a-b".parse::<ColumnRange>().is_err());
+    }
+
+    #[test]
+    fn test_delete() {
+        let content = "abcdef\nab\n\nabcdef";
+        let rectangle = rectangle("2-4", "");
+        let config = SearchConfig::default();
+        assert_eq!(
+            edits(content, &rectangle, config),
+            vec![(1, 3), (8, 1), (12, 3)]
+        );
+    }
+
+    #[test]
+    fn test_insert() {
+        let content = "abc\nab\na";
+        let rectangle = Rectangle {
+            start: 2,
+            end: 2,
+            text: b"|".to_vec(),
+        };
+        let config = SearchConfig::default();
+        assert_eq!(edits(content, &rectangle, config), vec![(2, 0), (6, 0)]);
+    }
+
+    #[test]
+    fn test_columns() {
+        let content = "été\nabc\r\n日本";
+        let rectangle = rectangle("2", "_");
+        let config = SearchConfig {
+            columns: ColumnMode::Chars,
+            ..SearchConfig::default()
+        };
+        assert_eq!(
+            edits(content, &rectangle, config),
+            vec![(2, 1), (7, 1), (14, 3)]
+        );
+        // the second column of the last line is in the middle of a wide character
+        let config = SearchConfig {
+            columns: ColumnMode::Width,
+            separator: "crlf".parse().unwrap(),
+            ..SearchConfig::default()
+        };
+        assert_eq!(edits(content, &rectangle, config), vec![(2, 1)]);
+    }
+
+    #[test]
+    fn test_tabstop() {
+        let content = "\tab\na\tb";
+        let rectangle = rectangle("5", "_");
+        let config = SearchConfig {
+            tabstop: NonZeroUsize::new(4),
+            ..SearchConfig::default()
+        };
+        assert_eq!(edits(content, &rectangle, config), vec![(1, 1), (6, 1)]);
+    }
+
+    #[test]
+    fn test_end_inside_char() {
+        // the edit stops before the wide character or the tab at the last column
+        let rectangle = rectangle("1-3", "");
+        let config = SearchConfig {
+            columns: ColumnMode::Width,
+            ..SearchConfig::default()
+        };
+        assert_eq!(
+            edits(
+                "ab日cd
+ab",
+                &rectangle,
+                config
+            ),
+            vec![(0, 2), (8, 2)]
+        );
+        let config = SearchConfig {
+            tabstop: NonZeroUsize::new(8),
+            ..SearchConfig::default()
+        };
+        assert_eq!(edits("a\tbcdef", &rectangle, config.clone()), vec![(0, 1)]);
+        // a tab taking all the columns is kept whole, a short line is edited up to its end
+        assert_eq!(edits("\tb\nab", &rectangle, config), vec![(3, 2)]);
+    }
+}
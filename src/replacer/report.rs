@@ -0,0 +1,173 @@
+use crate::replacer::checker::Hit;
+use crate::replacer::error::Error;
+use crate::replacer::pool::FileResults;
+use crate::replacer::select::Counts;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What a run did to a file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// The number of occurrences found in the file, and how many of them were not kept
+    pub counts: Counts,
+    /// The places of the occurrences kept, only filled by a dry run of a pattern
+    pub hits: Vec<Hit>,
+    pub stats: FileStats,
+    /// Why the file was left as it is without being searched to the end, if it was
+    pub skipped: Option<SkipReason>,
+}
+
+/// What was measured while processing a file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FileStats {
+    /// The bytes actually read from the file, up to where it was left when it was skipped
+    pub bytes_read: u64,
+    /// The size of the new content of the file, 0 for a dry run
+    pub bytes_written: u64,
+    pub duration: Duration,
+    /// Whether the new content of the file differs from the original, never true for a dry run:
+    /// replacing a text with itself leaves a file unchanged and untouched
+    pub changed: bool,
+}
+
+/// Why a file was left as it is without being searched to the end.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    /// The user quit an interactive run before the file
+    Quit,
+    /// Searching the file took more than the timeout of the options
+    Timeout,
+    /// The run was cancelled while the file was processed
+    Cancelled,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::Quit => "quit",
+            SkipReason::Timeout => "timeout",
+            SkipReason::Cancelled => "cancelled",
+        }
+    }
+
+    /// Returns why a file is skipped when processing it failed with `error`, if it is skipped.
+    pub(crate) fn from_error(error: &Error) -> Option<Self> {
+        match error.root_cause() {
+            Error::TimedOut(_) => Some(SkipReason::Timeout),
+            Error::Cancelled => Some(SkipReason::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl FileReport {
+    /// Returns the number of occurrences kept, the ones that are, or would be, replaced.
+    pub fn nb_kept(&self) -> usize {
+        self.counts.found - self.counts.skipped
+    }
+}
+
+/// The outcome of a run.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The files that were processed, in the order of the paths
+    pub files: Vec<FileReport>,
+    /// The errors of the files that could not be processed
+    pub errors: Vec<Error>,
+    /// The time taken by the whole run, the files may be processed in parallel
+    pub duration: Duration,
+}
+
+/// The sums of the statistics of the files of a run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Totals {
+    pub files: usize,
+    pub files_changed: usize,
+    pub files_skipped: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// The number of occurrences found
+    pub matches: usize,
+    /// The number of occurrences kept
+    pub replacements: usize,
+}
+
+impl Report {
+    /// Gathers the reports of the files of a run, and the errors of the ones that failed.
+    pub(crate) fn new(results: FileResults<FileReport>, duration: Duration) -> Self {
+        let mut report = Report {
+            duration,
+            ..Report::default()
+        };
+        for result in results {
+            match result {
+                Ok((_, file)) => report.files.push(file),
+                Err(e) => report.errors.push(e),
+            }
+        }
+        report
+    }
+
+    /// Returns the number of occurrences kept in all the files.
+    pub fn nb_kept(&self) -> usize {
+        self.files.iter().map(FileReport::nb_kept).sum()
+    }
+
+    /// Returns the number of occurrences skipped in all the files.
+    pub fn nb_skipped(&self) -> usize {
+        self.files.iter().map(|f| f.counts.skipped).sum()
+    }
+
+    /// Returns the sums of the counts and stats of all the files.
+    pub fn totals(&self) -> Totals {
+        let mut totals = Totals::default();
+        for file in &self.files {
+            totals.files += 1;
+            totals.files_changed += usize::from(file.stats.changed);
+            totals.files_skipped += usize::from(file.skipped.is_some());
+            totals.bytes_read += file.stats.bytes_read;
+            totals.bytes_written += file.stats.bytes_written;
+            totals.matches += file.counts.found;
+            totals.replacements += file.nb_kept();
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals() {
+        let file = |found, changed, skipped| FileReport {
+            counts: Counts { found, skipped: 1 },
+            stats: FileStats {
+                bytes_read: 10,
+                bytes_written: if changed { 12 } else { 0 },
+                duration: Duration::ZERO,
+                changed,
+            },
+            skipped,
+            ..FileReport::default()
+        };
+        let results = vec![
+            Ok((PathBuf::new(), file(3, true, None))),
+            Err(Error::NothingToDo),
+            Ok((PathBuf::new(), file(1, false, Some(SkipReason::Quit)))),
+        ];
+        let report = Report::new(results, Duration::ZERO);
+        assert_eq!(report.errors.len(), 1);
+        let totals = Totals {
+            files: 2,
+            files_changed: 1,
+            files_skipped: 1,
+            bytes_read: 20,
+            bytes_written: 12,
+            matches: 4,
+            replacements: 2,
+        };
+        assert_eq!(report.totals(), totals);
+    }
+}
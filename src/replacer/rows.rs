@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+/// How a row of a block pattern is found, besides being at the column of the first row.
+///
+/// The rules of the first row are ignored: it is always matched, where the block starts.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RowRule {
+    /// The row matches any line, whatever its text; nothing is replaced in it, so its replacement
+    /// must be empty
+    pub any: bool,
+    /// The row may be missing, the next row is then looked for where it would have been
+    pub optional: bool,
+    /// The number of lines that may be skipped before the row, the closest match is taken
+    pub max_gap: usize,
+    /// The range of columns, relative to the column of the first row, where the row may start
+    ///
+    /// The leftmost column where the row matches is taken.
+    pub min_delta: isize,
+    pub max_delta: isize,
+}
+
+/// A RowRule applied to a row of a block, starting at 1.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NumberedRule {
+    pub row: usize,
+    pub rule: RowRule,
+}
+
+impl NumberedRule {
+    /// Returns the rule of each row of a block, from a list of numbered rules.
+    ///
+    /// When several rules are given for the same row, the last one is used.
+    pub fn collect(rules: &[NumberedRule]) -> Vec<RowRule> {
+        let mut rows = Vec::new();
+        for numbered in rules {
+            if rows.len() < numbered.row {
+                rows.resize(numbered.row, RowRule::default());
+            }
+            rows[numbered.row - 1] = numbered.rule;
+        }
+        rows
+    }
+}
+
+impl NumberedRule {
+    /// Parses a rules file: one `ROW:RULE[,RULE...]` per line, as with `FromStr`. The blank
+    /// lines and the ones starting with `#` are ignored.
+    pub fn parse_file(text: &str) -> Result<Vec<NumberedRule>, String> {
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| line.parse().map_err(|e| format!("line {number}: {e}")))
+            .collect()
+    }
+}
+
+/// Parses `ROW:RULE[,RULE...]` where a rule is `any`, `optional`, `gap=N`, or `delta=D` or
+/// `delta=MIN..MAX` with signed deltas (`delta=+4`, `delta=-1..1`).
+impl FromStr for NumberedRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (row, rules) = s
+            .split_once(':')
+            .ok_or_else(|| format!("invalid row rule {s:?}, expected ROW:RULE[,RULE...]"))?;
+        let row = match row.parse::<usize>() {
+            Ok(0) | Err(_) => return Err(format!("invalid row number: {row:?}")),
+            Ok(n) => n,
+        };
+        let mut rule = RowRule::default();
+        for part in rules.split(',') {
+            match part.split_once('=') {
+                None if part == "any" => rule.any = true,
+                None if part == "optional" => rule.optional = true,
+                Some(("gap", n)) => {
+                    rule.max_gap = n.parse().map_err(|_| format!("invalid gap: {n:?}"))?
+                }
+                Some(("delta", delta)) => {
+                    (rule.min_delta, rule.max_delta) = parse_delta(delta)?;
+                }
+                _ => return Err(format!("unknown row rule: {part:?}")),
+            }
+        }
+        Ok(Self { row, rule })
+    }
+}
+
+fn parse_delta(s: &str) -> Result<(isize, isize), String> {
+    let parse = |d: &str| {
+        d.parse::<isize>()
+            .map_err(|_| format!("invalid delta: {s:?}"))
+    };
+    let (min, max) = match s.split_once("..") {
+        None => (parse(s)?, parse(s)?),
+        Some((min, max)) => (parse(min)?, parse(max)?),
+    };
+    if min > max {
+        return Err(format!("empty delta range: {s:?}"));
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let numbered: NumberedRule = "2:any,gap=3".parse().unwrap();
+        assert_eq!(numbered.row, 2);
+        assert_eq!(
+            numbered.rule,
+            RowRule {
+                any: true,
+                optional: false,
+                max_gap: 3,
+                min_delta: 0,
+                max_delta: 0,
+            }
+        );
+        let numbered: NumberedRule = "3:delta=+4".parse().unwrap();
+        assert_eq!((numbered.rule.min_delta, numbered.rule.max_delta), (4, 4));
+        let numbered: NumberedRule = "3:delta=-2..1".parse().unwrap();
+        assert_eq!((numbered.rule.min_delta, numbered.rule.max_delta), (-2, 1));
+        assert!("3:delta=2..1".parse::<NumberedRule>().is_err());
+        assert!("3:delta=x".parse::<NumberedRule>().is_err());
+        assert!("0:any".parse::<NumberedRule>().is_err());
+        assert!("2".parse::<NumberedRule>().is_err());
+        assert!("2:maybe".parse::<NumberedRule>().is_err());
+        assert!("2:gap=x".parse::<NumberedRule>().is_err());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let text = "# indented body\n2:delta=+4\n\n  3:any,gap=2  \n";
+        let rules = NumberedRule::parse_file(text).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!((rules[0].row, rules[0].rule.min_delta), (2, 4));
+        assert_eq!((rules[1].row, rules[1].rule.max_gap), (3, 2));
+        let e = NumberedRule::parse_file("2:any\n3:maybe").unwrap_err();
+        assert_eq!(e, "line 2: unknown row rule: \"maybe\"");
+    }
+
+    #[test]
+    fn test_collect() {
+        let rules = vec![
+            "3:optional".parse().unwrap(),
+            "2:gap=1".parse().unwrap(),
+            "3:any".parse().unwrap(),
+        ];
+        let rows = NumberedRule::collect(&rules);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], RowRule::default());
+        assert_eq!(rows[1].max_gap, 1);
+        assert!(rows[2].any && !rows[2].optional);
+    }
+}
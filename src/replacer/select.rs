@@ -0,0 +1,357 @@
+use crate::replacer::address::{Address, Regions};
+use crate::replacer::diff::Diff;
+use crate::replacer::error::Result;
+use crate::replacer::separator::Separator;
+use std::collections::VecDeque;
+use std::io::{BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Which occurrences of the patterns are kept.
+///
+/// An occurrence of a block pattern is kept or skipped as a whole: its rows are never split.
+/// The criteria are applied in the order of the fields.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Selection {
+    /// Only keep the occurrences that start in the regions of these addresses, if there are any
+    pub addresses: Vec<Address>,
+    /// Only keep the first occurrence that starts on each line
+    pub first_per_line: bool,
+    /// Only keep the nth occurrence of each file, starting at 1
+    pub nth: Option<usize>,
+    /// Keep at most this number of occurrences in each file
+    pub max_per_file: Option<usize>,
+    /// Keep at most this number of occurrences over all the files
+    pub max_total: Option<usize>,
+}
+
+impl Selection {
+    /// Whether the lines of the original stream must be read to select the occurrences.
+    pub fn reads_lines(&self) -> bool {
+        self.first_per_line || !self.addresses.is_empty()
+    }
+
+    /// Creates the budget shared by all the files of a run, if there is a global limit.
+    pub(crate) fn budget(&self) -> Option<AtomicUsize> {
+        self.max_total.map(AtomicUsize::new)
+    }
+}
+
+/// The number of occurrences found in a file, and how many of them were not kept.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    pub found: usize,
+    pub skipped: usize,
+}
+
+/// Filters the Diffs of a file according to a Selection.
+pub struct Select<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+    selection: &'iterator Selection,
+    budget: Option<&'iterator AtomicUsize>,
+    lines: Option<Regions<'iterator, BufReader<R>>>,
+    counts: &'iterator mut Counts,
+    /// The decisions taken on the first rows of block occurrences, for their other rows
+    decisions: VecDeque<(usize, bool)>,
+    nb_rows: usize,
+    nb_candidates: usize,
+    nb_kept: usize,
+    last_line: Option<usize>,
+}
+
+impl<'search, 'iterator, R> Select<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    /// Creates a filter over `diffs`, produced by a pattern of `nb_rows` rows.
+    ///
+    /// `original` must read the same content as the one the diffs were found in, with lines ended
+    /// by `separator`; it is only needed when `Selection::reads_lines` is true.
+    pub fn new(
+        diffs: Box<dyn Iterator<Item = Result<Diff<'search>>> + 'iterator>,
+        nb_rows: usize,
+        selection: &'iterator Selection,
+        budget: Option<&'iterator AtomicUsize>,
+        original: Option<R>,
+        separator: Separator,
+        counts: &'iterator mut Counts,
+    ) -> Self {
+        Self {
+            diffs,
+            selection,
+            budget,
+            lines: original
+                .map(|o| Regions::new(BufReader::new(o), &selection.addresses, separator)),
+            counts,
+            decisions: VecDeque::new(),
+            nb_rows,
+            nb_candidates: 0,
+            nb_kept: 0,
+            last_line: None,
+        }
+    }
+
+    fn next_selected(&mut self) -> Result<Option<Diff<'search>>> {
+        loop {
+            let diff = match self.diffs.next() {
+                None => return Ok(None),
+                Some(diff) => diff?,
+            };
+            let keep = if diff.row == 0 {
+                self.counts.found += 1;
+                let keep = self.select(&diff)?;
+                if !keep {
+                    self.counts.skipped += 1;
+                }
+                if self.nb_rows > 1 {
+                    self.decisions.push_back((diff.anchor, keep));
+                }
+                keep
+            } else {
+                self.decision(&diff)
+            };
+            if keep {
+                return Ok(Some(diff));
+            }
+        }
+    }
+
+    fn select(&mut self, diff: &Diff) -> Result<bool> {
+        if let Some(lines) = self.lines.as_mut() {
+            let (line, in_region) = lines.locate(diff.pos)?;
+            if !in_region {
+                return Ok(false);
+            }
+            if self.selection.first_per_line {
+                if self.last_line == Some(line) {
+                    return Ok(false);
+                }
+                self.last_line = Some(line);
+            }
+        }
+        self.nb_candidates += 1;
+        if self
+            .selection
+            .nth
+            .is_some_and(|nth| nth != self.nb_candidates)
+        {
+            return Ok(false);
+        }
+        if self
+            .selection
+            .max_per_file
+            .is_some_and(|max| self.nb_kept >= max)
+        {
+            return Ok(false);
+        }
+        if let Some(budget) = self.budget {
+            let taken =
+                budget.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| b.checked_sub(1));
+            if taken.is_err() {
+                return Ok(false);
+            }
+        }
+        self.nb_kept += 1;
+        Ok(true)
+    }
+
+    /// Returns the decision taken on the first row of the occurrence `diff` belongs to.
+    fn decision(&mut self, diff: &Diff) -> bool {
+        let index = self
+            .decisions
+            .iter()
+            .position(|(anchor, _)| *anchor == diff.anchor);
+        match index {
+            None => true,
+            Some(i) if diff.row + 1 == self.nb_rows => {
+                self.decisions.remove(i).is_some_and(|d| d.1)
+            }
+            Some(i) => self.decisions[i].1,
+        }
+    }
+}
+
+impl<'search, 'iterator, R> Iterator for Select<'search, 'iterator, R>
+where
+    R: Read,
+    'search: 'iterator,
+{
+    type Item = Result<Diff<'search>>;
+
+    fn next(&mut self) -> Option<Result<Diff<'search>>> {
+        self.next_selected().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CONTENT: &str = "abba abba\nabba\nabba abba";
+
+    fn diff(pos: usize, anchor: usize, row: usize) -> Result<Diff<'static>> {
+        Ok(Diff {
+            pos,
+            remove: 4,
+            add: "toto".as_bytes().into(),
+            anchor,
+            row,
+        })
+    }
+
+    fn single_row_diffs() -> Vec<Result<Diff<'static>>> {
+        [0, 5, 10, 15, 20]
+            .into_iter()
+            .map(|pos| diff(pos, pos, 0))
+            .collect()
+    }
+
+    fn selected(
+        diffs: Vec<Result<Diff<'static>>>,
+        nb_rows: usize,
+        selection: &Selection,
+        budget: Option<&AtomicUsize>,
+    ) -> (Vec<usize>, Counts) {
+        let mut counts = Counts::default();
+        let positions = Select::new(
+            Box::new(diffs.into_iter()),
+            nb_rows,
+            selection,
+            budget,
+            Some(Cursor::new(CONTENT)),
+            Separator::default(),
+            &mut counts,
+        )
+        .map(|d| d.unwrap().pos)
+        .collect();
+        (positions, counts)
+    }
+
+    #[test]
+    fn test_all() {
+        let (positions, counts) = selected(single_row_diffs(), 1, &Selection::default(), None);
+        assert_eq!(positions, vec![0, 5, 10, 15, 20]);
+        assert_eq!(
+            counts,
+            Counts {
+                found: 5,
+                skipped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_per_file() {
+        let selection = Selection {
+            max_per_file: Some(2),
+            ..Selection::default()
+        };
+        let (positions, counts) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![0, 5]);
+        assert_eq!(
+            counts,
+            Counts {
+                found: 5,
+                skipped: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_nth() {
+        let selection = Selection {
+            nth: Some(3),
+            ..Selection::default()
+        };
+        let (positions, counts) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![10]);
+        assert_eq!(counts.skipped, 4);
+    }
+
+    #[test]
+    fn test_first_per_line() {
+        let selection = Selection {
+            first_per_line: true,
+            ..Selection::default()
+        };
+        let (positions, _) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![0, 10, 15]);
+    }
+
+    #[test]
+    fn test_first_per_line_then_nth() {
+        let selection = Selection {
+            first_per_line: true,
+            nth: Some(2),
+            ..Selection::default()
+        };
+        let (positions, _) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![10]);
+    }
+
+    #[test]
+    fn test_addresses() {
+        let selection = Selection {
+            addresses: vec![Address::lines(2, 3)],
+            ..Selection::default()
+        };
+        let (positions, counts) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![10, 15, 20]);
+        assert_eq!(
+            counts,
+            Counts {
+                found: 5,
+                skipped: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_addresses_then_nth() {
+        let selection = Selection {
+            addresses: vec![Address::lines(3, 3)],
+            nth: Some(2),
+            ..Selection::default()
+        };
+        let (positions, _) = selected(single_row_diffs(), 1, &selection, None);
+        assert_eq!(positions, vec![20]);
+    }
+
+    #[test]
+    fn test_budget_is_shared() {
+        let selection = Selection {
+            max_total: Some(3),
+            ..Selection::default()
+        };
+        let budget = selection.budget();
+        let (positions, _) = selected(single_row_diffs(), 1, &selection, budget.as_ref());
+        assert_eq!(positions, vec![0, 5, 10]);
+        let (positions, counts) = selected(single_row_diffs(), 1, &selection, budget.as_ref());
+        assert!(positions.is_empty());
+        assert_eq!(counts.skipped, 5);
+    }
+
+    #[test]
+    fn test_block_rows_follow_first_row() {
+        // two interleaved occurrences of a 2-row block, like "abba abba\nabba abba"
+        let diffs = vec![diff(0, 0, 0), diff(5, 5, 0), diff(10, 0, 1), diff(15, 5, 1)];
+        let selection = Selection {
+            nth: Some(2),
+            ..Selection::default()
+        };
+        let (positions, counts) = selected(diffs, 2, &selection, None);
+        assert_eq!(positions, vec![5, 15]);
+        assert_eq!(
+            counts,
+            Counts {
+                found: 2,
+                skipped: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+/// The sequence of bytes that ends a line, or a record, when matching blocks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Separator {
+    bytes: Vec<u8>,
+}
+
+impl Separator {
+    /// Returns a separator made of the given bytes, there is none if they are empty.
+    pub fn new(bytes: Vec<u8>) -> Option<Self> {
+        if bytes.is_empty() {
+            None
+        } else {
+            Some(Self { bytes })
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Always false: a separator has at least one byte.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns whether `bytes` start with the separator.
+    pub fn starts(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(&self.bytes)
+    }
+
+    /// Returns whether `bytes` end with the separator.
+    pub fn ends(&self, bytes: &[u8]) -> bool {
+        bytes.ends_with(&self.bytes)
+    }
+
+    /// Returns the last byte of the separator.
+    pub fn last_byte(&self) -> u8 {
+        self.bytes[self.bytes.len() - 1]
+    }
+
+    /// Returns the index of the first separator in `bytes`.
+    pub fn find(&self, bytes: &[u8]) -> Option<usize> {
+        match self.bytes.as_slice() {
+            [byte] => bytes.iter().position(|b| b == byte),
+            separator => bytes
+                .windows(separator.len())
+                .position(|window| window == separator),
+        }
+    }
+}
+
+/// Finds the separators of a stream read in chunks, including the ones split between two chunks.
+#[derive(Clone, Debug)]
+pub struct SeparatorFinder {
+    separator: Separator,
+    /// The last bytes of the chunks since the last separator, that may start a separator
+    tail: Vec<u8>,
+}
+
+impl SeparatorFinder {
+    pub fn new(separator: Separator) -> Self {
+        Self {
+            separator,
+            tail: Vec::new(),
+        }
+    }
+
+    /// Returns the offset in `chunk` of the end of the first separator ending in it, the start of
+    /// that separator may be in the chunks given to `skip` before.
+    ///
+    /// Once a separator is found, the search starts again right after it.
+    pub fn find_end(&mut self, chunk: &[u8]) -> Option<usize> {
+        let len = self.separator.len();
+        if !self.tail.is_empty() {
+            let tail_len = self.tail.len();
+            let mut window = std::mem::take(&mut self.tail);
+            window.extend_from_slice(&chunk[..chunk.len().min(len - 1)]);
+            if let Some(start) = self.separator.find(&window) {
+                return Some(start + len - tail_len);
+            }
+            window.truncate(tail_len);
+            self.tail = window;
+        }
+        let end = self.separator.find(chunk).map(|start| start + len)?;
+        self.tail.clear();
+        Some(end)
+    }
+
+    /// Goes over bytes without any separator ending in them, following the last separator found.
+    pub fn skip(&mut self, bytes: &[u8]) {
+        self.tail.extend_from_slice(bytes);
+        let keep = (self.separator.len() - 1).min(self.tail.len());
+        self.tail.drain(..self.tail.len() - keep);
+    }
+}
+
+impl Default for Separator {
+    fn default() -> Self {
+        Self {
+            bytes: b"\n".to_vec(),
+        }
+    }
+}
+
+impl FromStr for Separator {
+    type Err = String;
+
+    /// Parses `lf`, `crlf`, `cr`, `nul`, or any other text taken as it is.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let bytes = match s {
+            "lf" => b"\n".to_vec(),
+            "crlf" => b"\r\n".to_vec(),
+            "cr" => b"\r".to_vec(),
+            "nul" => b"\0".to_vec(),
+            _ => s.as_bytes().to_vec(),
+        };
+        Separator::new(bytes).ok_or_else(|| "empty separator".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("lf".parse(), Ok(Separator::default()));
+        assert_eq!(
+            "crlf".parse::<Separator>().ok(),
+            Separator::new(b"\r\n".to_vec())
+        );
+        assert_eq!("nul".parse::<Separator>().ok(), Separator::new(vec![0]));
+        assert_eq!(
+            "||".parse::<Separator>().ok(),
+            Separator::new(b"||".to_vec())
+        );
+        assert!("".parse::<Separator>().is_err());
+        assert!(Separator::new(Vec::new()).is_none());
+        assert!(!Separator::default().is_empty());
+    }
+
+    /// Returns the ends of the separators found in `chunks`, as offsets in their concatenation.
+    fn ends(separator: &str, chunks: &[&str]) -> Vec<usize> {
+        let mut finder = SeparatorFinder::new(separator.parse().unwrap());
+        let mut ends = Vec::new();
+        let mut offset = 0;
+        for chunk in chunks {
+            let mut rest = chunk.as_bytes();
+            let mut rest_offset = offset;
+            while let Some(end) = finder.find_end(rest) {
+                ends.push(rest_offset + end);
+                rest = &rest[end..];
+                rest_offset += end;
+            }
+            finder.skip(rest);
+            offset += chunk.len();
+        }
+        ends
+    }
+
+    #[test]
+    fn test_finder() {
+        assert_eq!(ends("crlf", &["a\r\nb\r\n"]), vec![3, 6]);
+        assert_eq!(ends("crlf", &["a\r", "\nb\r", "x\n"]), vec![3]);
+        assert_eq!(ends("<=>", &["a<", "=", ">b<=>"]), vec![4, 8]);
+        assert_eq!(ends("lf", &["a", "\n", ""]), vec![2]);
+    }
+
+    #[test]
+    fn test_find() {
+        let separator = Separator::new(b"\r\n".to_vec()).unwrap();
+        assert_eq!(separator.find(b"ab\rc\r\nd"), Some(4));
+        assert_eq!(separator.find(b"ab\r"), None);
+        assert!(separator.starts(b"\r\nd"));
+        assert_eq!(Separator::default().find(b"ab\nc"), Some(2));
+    }
+}
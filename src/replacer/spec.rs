@@ -0,0 +1,171 @@
+use crate::replacer::bufsearcher::{BufSearcher, SearchConfig};
+use crate::replacer::diff::Diff;
+use crate::replacer::error::{Error, Result};
+use crate::replacer::matcher::Search;
+use std::io::Read;
+use std::sync::Arc;
+
+/// What a search looks for and what it puts in its place: the rows of a pattern along with their
+/// replacements, a single row for a literal.
+///
+/// A spec owns its bytes and is checked once when it is built. It is cheap to clone, the clones
+/// share the same rows, and it can be moved to other threads or kept for as long as needed, so
+/// that a spec built once can serve any number of runs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SearchSpec {
+    rows: Arc<Rows>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Rows {
+    patterns: Vec<Vec<u8>>,
+    /// As many as the patterns
+    replacements: Vec<Vec<u8>>,
+}
+
+impl SearchSpec {
+    /// Returns a spec replacing `pattern` with `replacement`, both given as text or as bytes.
+    ///
+    /// Fails with `Error::EmptyPattern` when the pattern is empty.
+    pub fn literal(pattern: impl Into<Vec<u8>>, replacement: impl Into<Vec<u8>>) -> Result<Self> {
+        Self::new([(pattern, replacement)])
+    }
+
+    /// Returns a spec replacing a block, given as its rows along with their replacements.
+    ///
+    /// Fails with `Error::NothingToDo` when there is no row, and with `Error::EmptyPattern` when
+    /// a row is empty.
+    pub fn new<I, P, R>(rows: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (P, R)>,
+        P: Into<Vec<u8>>,
+        R: Into<Vec<u8>>,
+    {
+        let (patterns, replacements): (Vec<_>, Vec<_>) =
+            rows.into_iter().map(|(p, r)| (p.into(), r.into())).unzip();
+        if patterns.is_empty() {
+            return Err(Error::NothingToDo);
+        }
+        if let Some(row) = patterns.iter().position(Vec::is_empty) {
+            return Err(Error::EmptyPattern(row + 1));
+        }
+        Ok(Self {
+            rows: Arc::new(Rows {
+                patterns,
+                replacements,
+            }),
+        })
+    }
+
+    /// Returns a spec only searching the rows of a pattern, for checks and computed replacements.
+    ///
+    /// The replacements of the rows are empty. Fails like `new`.
+    pub fn search<I, P>(patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Vec<u8>>,
+    {
+        Self::new(patterns.into_iter().map(|p| (p, Vec::new())))
+    }
+
+    /// Returns the rows of the pattern.
+    pub fn patterns(&self) -> &[Vec<u8>] {
+        &self.rows.patterns
+    }
+
+    /// Returns the replacements of the rows of the pattern.
+    pub fn replacements(&self) -> &[Vec<u8>] {
+        &self.rows.replacements
+    }
+
+    /// Returns the number of rows of the pattern, 1 for a literal.
+    pub fn nb_rows(&self) -> usize {
+        self.rows.patterns.len()
+    }
+}
+
+/// The patterns of a spec are searched with the block search of ved, which reads the stream by
+/// itself.
+impl Search for SearchSpec {
+    fn nb_rows(&self) -> usize {
+        self.rows.patterns.len()
+    }
+
+    fn diffs<'a>(
+        &'a self,
+        config: &SearchConfig,
+        reader: Box<dyn Read + 'a>,
+    ) -> Box<dyn Iterator<Item = Result<Diff<'a>>> + 'a> {
+        let (patterns, replacements) = (self.patterns(), self.replacements());
+        Box::new(BufSearcher::new(
+            patterns,
+            replacements,
+            config.clone(),
+            reader,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::num::NonZeroUsize;
+    use std::thread;
+
+    #[test]
+    fn test_new() {
+        let spec = SearchSpec::new([("who", "queen"), ("abba", "")]).unwrap();
+        assert_eq!(spec.patterns(), [b"who".to_vec(), b"abba".to_vec()]);
+        assert_eq!(spec.replacements(), [b"queen".to_vec(), Vec::new()]);
+        assert_eq!(spec.nb_rows(), 2);
+        let rows: [(&str, &str); 0] = [];
+        assert!(matches!(SearchSpec::new(rows), Err(Error::NothingToDo)));
+        let rows = [("who", "queen"), ("", "abba")];
+        assert!(matches!(SearchSpec::new(rows), Err(Error::EmptyPattern(2))));
+        assert!(matches!(
+            SearchSpec::literal("", "abba"),
+            Err(Error::EmptyPattern(1))
+        ));
+        assert!(matches!(
+            SearchSpec::search(["abba", ""]),
+            Err(Error::EmptyPattern(2))
+        ));
+        let spec = SearchSpec::search([&b"\x00\xff"[..]]).unwrap();
+        assert_eq!(spec.replacements(), [Vec::new()]);
+    }
+
+    #[test]
+    fn test_diffs() {
+        let spec = SearchSpec::literal("abba", "toto").unwrap();
+        let reader = || Box::new(Cursor::new("abba abba"));
+        let config = SearchConfig::default();
+        let positions: Vec<_> = spec
+            .diffs(&config, reader())
+            .map(|d| d.unwrap().pos)
+            .collect();
+        assert_eq!(positions, vec![0, 5]);
+        // the search uses the config given, and its errors are not dropped
+        let config = SearchConfig {
+            window: NonZeroUsize::new(2),
+            ..SearchConfig::default()
+        };
+        let mut diffs = spec.diffs(&config, reader());
+        assert!(matches!(
+            diffs.next(),
+            Some(Err(Error::WindowTooSmall {
+                pattern: 4,
+                window: 2
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_shared() {
+        let spec = SearchSpec::literal("abba", "toto").unwrap();
+        let clone = spec.clone();
+        assert!(Arc::ptr_eq(&spec.rows, &clone.rows));
+        let patterns = thread::spawn(move || clone.patterns().to_vec());
+        assert_eq!(patterns.join().unwrap(), spec.patterns());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::replacer::error::{Error, Operation, Result};
+use std::fs;
+use std::fs::ReadDir;
+use std::path::PathBuf;
+
+/// Depth-first iterator over the files contained in a directory hierarchy.
+///
+/// Directories are not yielded, only the entries that are not directories.
+/// An error on one entry (unreadable directory, failing metadata, ...) is yielded in place of that
+/// entry and the walk continues with the next one. The error is about the directory being listed.
+pub struct DirWalker {
+    /// The directories being listed, along with their paths
+    stack: Vec<(PathBuf, ReadDir)>,
+    pending: Option<PathBuf>,
+}
+
+impl DirWalker {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            stack: Vec::new(),
+            pending: Some(root),
+        }
+    }
+
+    fn next_entry(&mut self) -> Option<Result<PathBuf>> {
+        loop {
+            if let Some(path) = self.pending.take() {
+                if !path.is_dir() {
+                    return Some(Ok(path));
+                }
+                match fs::read_dir(&path) {
+                    Ok(read_dir) => self.stack.push((path, read_dir)),
+                    Err(e) => return Some(Err(Error::from(e).in_file(&path, Operation::Walk))),
+                }
+            }
+            let (dir, read_dir) = self.stack.last_mut()?;
+            match read_dir.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Err(e)) => return Some(Err(Error::from(e).in_file(dir, Operation::Walk))),
+                Some(Ok(entry)) => self.pending = Some(entry.path()),
+            }
+        }
+    }
+}
+
+impl Iterator for DirWalker {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Result<PathBuf>> {
+        self.next_entry()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_single_file() {
+        let dir = temp_dir();
+        let path = dir.path().join("file");
+        write_file(&path, "abba");
+        let paths: Vec<_> = DirWalker::new(path.clone()).map(|p| p.unwrap()).collect();
+        assert_eq!(paths, vec![path]);
+    }
+
+    #[test]
+    fn test_nested_dirs() {
+        let dir = temp_dir();
+        let child_dir = dir.path().join("child");
+        assert!(fs::create_dir(&child_dir).is_ok());
+        let grandchild_dir = child_dir.join("grandchild");
+        assert!(fs::create_dir(&grandchild_dir).is_ok());
+        let file1 = dir.path().join("file1");
+        write_file(&file1, "abba");
+        let file2 = child_dir.join("file2");
+        write_file(&file2, "abba");
+        let file3 = grandchild_dir.join("file3");
+        write_file(&file3, "abba");
+
+        let mut paths: Vec<_> = DirWalker::new(dir.path().to_owned())
+            .map(|p| p.unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec![file2, file3, file1]);
+    }
+
+    #[test]
+    fn test_empty_dir() {
+        let dir = temp_dir();
+        let paths: Vec<_> = DirWalker::new(dir.path().to_owned()).collect();
+        assert!(paths.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir();
+        let child_dir = dir.path().join("child");
+        assert!(fs::create_dir(&child_dir).is_ok());
+        let file = dir.path().join("file");
+        write_file(&file, "abba");
+        let permissions = fs::Permissions::from_mode(0o000);
+        assert!(fs::set_permissions(&child_dir, permissions).is_ok());
+        if fs::read_dir(&child_dir).is_ok() {
+            // permissions are not enforced, when running as root
+            return;
+        }
+
+        let mut results: Vec<_> = DirWalker::new(dir.path().to_owned()).collect();
+        let permissions = fs::Permissions::from_mode(0o755);
+        assert!(fs::set_permissions(&child_dir, permissions).is_ok());
+        results.sort_by_key(|r| r.is_ok());
+        assert_eq!(results.len(), 2);
+        let error = results[0].as_ref().unwrap_err();
+        assert_eq!(error.path(), Some(child_dir.as_path()));
+        assert!(matches!(
+            error,
+            Error::File {
+                operation: Operation::Walk,
+                ..
+            }
+        ));
+        assert_eq!(results[1].as_ref().unwrap(), &file);
+    }
+
+    #[test]
+    fn test_missing_root() {
+        let dir = temp_dir();
+        let path = dir.path().join("missing");
+        let paths: Vec<_> = DirWalker::new(path.clone()).map(|p| p.unwrap()).collect();
+        assert_eq!(paths, vec![path]);
+    }
+
+    fn temp_dir() -> tempfile::TempDir {
+        let result = tempfile::tempdir();
+        assert!(result.is_ok());
+        result.unwrap()
+    }
+
+    fn write_file<P: AsRef<Path>>(path: P, content: &str) {
+        let result = fs::write(path, content);
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,256 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ved::{FileReport, Progress};
+
+/// The shortest time between two redraws of the status line.
+const REFRESH: Duration = Duration::from_millis(200);
+
+/// The shortest time between two plain lines, which pile up in the output.
+const PLAIN_REFRESH: Duration = Duration::from_secs(2);
+
+/// A line showing the progress of a run, redrawn in place as the files are processed.
+///
+/// When the output is not a terminal, plain lines are written from time to time instead.
+pub struct StatusLine<W: Write + Send> {
+    output: Mutex<Drawn<W>>,
+    in_place: bool,
+    start: Instant,
+    files: AtomicUsize,
+    /// The total size of the files started
+    size: AtomicU64,
+    /// The bytes written as they are by the replacements
+    processed: AtomicU64,
+    /// The size of the files finished, for the runs that do not tell the bytes processed
+    finished: AtomicU64,
+    replacements: AtomicUsize,
+}
+
+struct Drawn<W> {
+    output: W,
+    last: Option<Instant>,
+}
+
+impl<W: Write + Send> StatusLine<W> {
+    /// Returns a status line redrawn in place, with carriage returns and ANSI escapes.
+    pub fn new(output: W) -> Self {
+        Self::with_mode(output, true)
+    }
+
+    /// Returns a status line written as plain lines, for an output that is not a terminal.
+    pub fn plain(output: W) -> Self {
+        Self::with_mode(output, false)
+    }
+
+    fn with_mode(output: W, in_place: bool) -> Self {
+        Self {
+            output: Mutex::new(Drawn { output, last: None }),
+            in_place,
+            start: Instant::now(),
+            files: AtomicUsize::new(0),
+            size: AtomicU64::new(0),
+            processed: AtomicU64::new(0),
+            finished: AtomicU64::new(0),
+            replacements: AtomicUsize::new(0),
+        }
+    }
+
+    /// Draws the line a last time and ends it, so that the next output starts on a line of its
+    /// own.
+    pub fn finish(&self) {
+        let mut drawn = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        let line = self.status().text();
+        // The progress is only shown, an error writing it does not stop the run
+        let _ = if self.in_place {
+            write!(drawn.output, "\r{line}\x1b[K\n")
+        } else {
+            writeln!(drawn.output, "{line}")
+        };
+        let _ = drawn.output.flush();
+    }
+
+    /// Redraws the line, unless it was drawn very recently.
+    fn draw(&self) {
+        let mut drawn = match self.output.try_lock() {
+            Ok(drawn) => drawn,
+            Err(_) => return,
+        };
+        let now = Instant::now();
+        let refresh = if self.in_place {
+            REFRESH
+        } else {
+            PLAIN_REFRESH
+        };
+        match drawn.last {
+            Some(last) if now - last < refresh => return,
+            // A plain line is only written once there is some progress to show
+            None if !self.in_place => drawn.last = Some(now),
+            _ => {
+                drawn.last = Some(now);
+                let line = self.status().text();
+                let _ = if self.in_place {
+                    write!(drawn.output, "\r{line}\x1b[K")
+                } else {
+                    writeln!(drawn.output, "{line}")
+                };
+                let _ = drawn.output.flush();
+            }
+        }
+    }
+
+    fn status(&self) -> Status {
+        let processed = self.processed.load(Ordering::Relaxed);
+        let finished = self.finished.load(Ordering::Relaxed);
+        Status {
+            files: self.files.load(Ordering::Relaxed),
+            bytes: processed.max(finished),
+            size: self.size.load(Ordering::Relaxed),
+            replacements: self.replacements.load(Ordering::Relaxed),
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+impl<W: Write + Send> Progress for StatusLine<W> {
+    fn file_started(&self, _path: &Path, size: u64) {
+        self.size.fetch_add(size, Ordering::Relaxed);
+        self.draw();
+    }
+
+    fn bytes_processed(&self, nb_bytes: u64) {
+        self.processed.fetch_add(nb_bytes, Ordering::Relaxed);
+        self.draw();
+    }
+
+    fn file_finished(&self, report: &FileReport) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.finished
+            .fetch_add(report.stats.bytes_read, Ordering::Relaxed);
+        self.replacements
+            .fetch_add(report.nb_kept(), Ordering::Relaxed);
+        self.draw();
+    }
+}
+
+/// What the status line shows.
+#[derive(Debug)]
+struct Status {
+    files: usize,
+    bytes: u64,
+    size: u64,
+    replacements: usize,
+    elapsed: Duration,
+}
+
+impl Status {
+    fn text(&self) -> String {
+        let seconds = self.elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        };
+        let mut text = format!(
+            "{} files, {} of {}, {}/s, {} replaced",
+            self.files,
+            text_size(self.bytes as f64),
+            text_size(self.size as f64),
+            text_size(rate),
+            self.replacements
+        );
+        if rate > 0.0 && self.size > self.bytes {
+            let left = (self.size - self.bytes) as f64 / rate;
+            text.push_str(&format!(", {} left", text_duration(left as u64)));
+        }
+        text
+    }
+}
+
+fn text_size(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024.0 {
+        return format!("{bytes:.0} B");
+    }
+    let mut size = bytes / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn text_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use ved::FileStats;
+
+    #[test]
+    fn test_status_text() {
+        let status = Status {
+            files: 3,
+            bytes: 2 * 1024 * 1024,
+            size: 6 * 1024 * 1024,
+            replacements: 12,
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(
+            status.text(),
+            "3 files, 2.0 MiB of 6.0 MiB, 1.0 MiB/s, 12 replaced, 4s left"
+        );
+        let done = Status {
+            bytes: 100,
+            size: 100,
+            ..status
+        };
+        assert_eq!(done.text(), "3 files, 100 B of 100 B, 50 B/s, 12 replaced");
+    }
+
+    #[test]
+    fn test_text_duration() {
+        assert_eq!(text_duration(59), "59s");
+        assert_eq!(text_duration(65), "1m05s");
+        assert_eq!(text_duration(7322), "2h02m");
+    }
+
+    #[test]
+    fn test_finish() {
+        let status = StatusLine::new(Vec::new());
+        status.file_started(Path::new("a"), 10);
+        status.file_finished(&FileReport {
+            path: PathBuf::from("a"),
+            stats: FileStats {
+                bytes_read: 10,
+                ..FileStats::default()
+            },
+            ..FileReport::default()
+        });
+        status.finish();
+        let output = status.output.into_inner().unwrap().output;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\r0 files, 0 B of 10 B"));
+        assert!(output.contains("\r1 files, 10 B of 10 B"));
+        assert!(output.ends_with(" replaced\x1b[K\n"));
+    }
+
+    #[test]
+    fn test_finish_plain() {
+        let status = StatusLine::plain(Vec::new());
+        status.file_started(Path::new("a"), 10);
+        status.finish();
+        let output = status.output.into_inner().unwrap().output;
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "0 files, 0 B of 10 B, 0 B/s, 0 replaced\n");
+    }
+}